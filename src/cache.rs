@@ -0,0 +1,97 @@
+use crate::command;
+use crate::error::Error;
+use crate::octomate::Repository;
+use crate::rest;
+use octocrab::models;
+use octocrab::Octocrab;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+/// Memoize the repository metadata fetched while running a batch,
+/// so several commands targeting the same repository fetch it only once.
+#[derive(Debug, Default)]
+pub struct RepositoryCache {
+    repositories: Mutex<HashMap<String, Arc<OnceCell<models::Repository>>>>,
+    assignees: Mutex<HashMap<String, Arc<OnceCell<bool>>>>,
+    labels: Mutex<HashMap<String, Arc<OnceCell<Vec<models::Label>>>>>,
+}
+
+impl RepositoryCache {
+    pub async fn repository(
+        &self,
+        octocrab: &Octocrab,
+        repository: &Repository,
+    ) -> Result<models::Repository, Error> {
+        let cell = self
+            .repositories
+            .lock()
+            .unwrap()
+            .entry(format!("{}/{}", repository.owner, repository.name))
+            .or_default()
+            .clone();
+
+        let metadata = cell
+            .get_or_try_init(|| async {
+                rest::timed(octocrab.repos(&repository.owner, &repository.name).get()).await
+            })
+            .await?;
+        Ok(metadata.clone())
+    }
+
+    /// Replace the metadata of the repository with the one Github answered a change with,
+    /// so the commands coming next see the change.
+    pub fn update_repository(&self, repository: &Repository, metadata: models::Repository) {
+        self.repositories.lock().unwrap().insert(
+            format!("{}/{}", repository.owner, repository.name),
+            Arc::new(OnceCell::new_with(Some(metadata))),
+        );
+    }
+
+    /// Whether `assignee` can be assigned to the issues of the repository.
+    pub async fn is_assignable(
+        &self,
+        octocrab: &Octocrab,
+        repository: &Repository,
+        assignee: &str,
+    ) -> Result<bool, Error> {
+        let cell = self
+            .assignees
+            .lock()
+            .unwrap()
+            .entry(format!(
+                "{}/{}/{}",
+                repository.owner, repository.name, assignee
+            ))
+            .or_default()
+            .clone();
+
+        let assignable = cell
+            .get_or_try_init(|| async {
+                let issues = octocrab.issues(&repository.owner, &repository.name);
+                rest::timed(issues.check_assignee(assignee)).await
+            })
+            .await?;
+        Ok(*assignable)
+    }
+
+    /// The labels of the repository, for the repositories read but not changed by the batch.
+    pub async fn labels(
+        &self,
+        octocrab: &Octocrab,
+        repository: &Repository,
+    ) -> Result<Vec<models::Label>, Error> {
+        let cell = self
+            .labels
+            .lock()
+            .unwrap()
+            .entry(format!("{}/{}", repository.owner, repository.name))
+            .or_default()
+            .clone();
+
+        let labels = cell
+            .get_or_try_init(|| command::list_labels(octocrab, repository))
+            .await?;
+        Ok(labels.clone())
+    }
+}
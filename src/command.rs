@@ -1,116 +1,892 @@
 use crate::error::Error;
-use crate::octomate::Context;
-use octocrab::models::{gists::Gist, issues::Issue, teams::Team, Label};
+use crate::graphql;
+use crate::octomate::{Context, Job, Repository, Settings};
+use crate::palette;
+use crate::provenance;
+use crate::rest;
+use crate::retry::RetryPolicy;
+use chrono::SubsecRound;
+use futures::stream::{FuturesUnordered, StreamExt};
+use octocrab::models::{
+    gists::Gist, issues::Issue, repos::ContentItems, repos::Object, repos::Ref, teams::Team, Label,
+    Milestone,
+};
+use octocrab::params::repos::Reference;
 use octocrab::Octocrab;
+use octocrab::Page;
 use paris::info;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub enum Command {
-    CreateLabel(CreateLabelOptions),
+    CreateLabel(CreateLabels),
+    DeleteLabel(DeleteLabelOptions),
+    CopyLabels(CopyLabelsOptions),
     CreateIssue(CreateIssueOptions),
+    CreateMilestone(CreateMilestoneOptions),
+    SetIssueMilestone(SetIssueMilestoneOptions),
+    CreateIssueFromTemplate(CreateIssueFromTemplateOptions),
     CreateTeam(CreateTeamOptions),
+    AddTeamRepository(AddTeamRepositoryOptions),
+    SyncCodeownersTeam(SyncCodeownersTeamOptions),
+    SyncTeamRepos(SyncTeamReposOptions),
+    SetOrgDefaultPermission(SetOrgDefaultPermissionOptions),
     CreateGist(CreateGistOptions),
+    UpdateGist(UpdateGistOptions),
+    CreateBranch(CreateBranchOptions),
+    EnableAutoMerge(EnableAutoMergeOptions),
+    LinkRepositoryToProject(LinkRepositoryToProjectOptions),
+    UpdateBranchProtection(UpdateBranchProtectionOptions),
+    SetRepositoryVisibility(SetRepositoryVisibilityOptions),
+    SetRepositoryInfo(SetRepositoryInfoOptions),
+    SetActionsPermissions(SetActionsPermissionsOptions),
+    SetSubscription(SetSubscriptionOptions),
+    CreateRuleset(CreateRulesetOptions),
+    WaitForWorkflow(WaitForWorkflowOptions),
+    CreateAutolink(CreateAutolinkOptions),
 }
 
 impl Command {
-    pub async fn run(
+    /// Name of the command, as written in the batch file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CreateLabel(_) => "create-label",
+            Self::DeleteLabel(_) => "delete-label",
+            Self::CopyLabels(_) => "copy-labels",
+            Self::CreateIssue(_) => "create-issue",
+            Self::CreateMilestone(_) => "create-milestone",
+            Self::SetIssueMilestone(_) => "set-issue-milestone",
+            Self::CreateIssueFromTemplate(_) => "create-issue-from-template",
+            Self::CreateTeam(_) => "create-team",
+            Self::AddTeamRepository(_) => "add-team-repository",
+            Self::SyncCodeownersTeam(_) => "sync-codeowners-team",
+            Self::SyncTeamRepos(_) => "sync-team-repos",
+            Self::SetOrgDefaultPermission(_) => "set-org-default-permission",
+            Self::CreateGist(_) => "create-gist",
+            Self::UpdateGist(_) => "update-gist",
+            Self::CreateBranch(_) => "create-branch",
+            Self::EnableAutoMerge(_) => "enable-auto-merge",
+            Self::LinkRepositoryToProject(_) => "link-repository-to-project",
+            Self::UpdateBranchProtection(_) => "update-branch-protection",
+            Self::SetRepositoryVisibility(_) => "set-repository-visibility",
+            Self::SetRepositoryInfo(_) => "set-repository-info",
+            Self::SetActionsPermissions(_) => "set-actions-permissions",
+            Self::SetSubscription(_) => "set-subscription",
+            Self::CreateRuleset(_) => "create-ruleset",
+            Self::WaitForWorkflow(_) => "wait-for-workflow",
+            Self::CreateAutolink(_) => "create-autolink",
+        }
+    }
+
+    /// Whether running the command twice has the same effect as running it once,
+    /// so it can be retried whatever the error. The commands creating things with a POST
+    /// aren't: creating an issue twice creates two issues, creating a label twice fails
+    /// the second time.
+    pub fn is_idempotent(&self) -> bool {
+        !matches!(
+            self,
+            Self::CreateLabel(_)
+                | Self::CreateIssue(_)
+                | Self::CreateIssueFromTemplate(_)
+                | Self::CreateMilestone(_)
+                | Self::CreateTeam(_)
+                | Self::CreateGist(_)
+                | Self::CreateBranch(_)
+                | Self::CreateRuleset(_)
+                | Self::CreateAutolink(_)
+        )
+    }
+
+    /// Whether the command runs once per repository of the job, rather than once per job.
+    pub fn is_per_repository(&self) -> bool {
+        !matches!(
+            self,
+            Self::CreateTeam(_)
+                | Self::SyncTeamRepos(_)
+                | Self::CreateGist(_)
+                | Self::UpdateGist(_)
+                | Self::SetOrgDefaultPermission(_)
+        )
+    }
+
+    pub async fn run(&self, octocrab: &Octocrab, ctx: &Context<'_>) -> Vec<Outcome> {
+        info!("run: {:?}", self);
+        let ctx = &ctx.update_from_command(self);
+        if ctx.settings.dry_run {
+            return match self {
+                // Reading the labels is harmless, and gives a real preview of the changes.
+                Self::CopyLabels(options) => {
+                    for_each_repository(ctx, |repository| {
+                        options.preview(octocrab, ctx, repository)
+                    })
+                    .await
+                }
+                _ => self.dry_run(ctx),
+            };
+        }
+        match self {
+            Self::CreateLabel(labels) => {
+                for_each_repository_and(ctx, labels.labels(), |repository, options| {
+                    options.run(octocrab, ctx, repository)
+                })
+                .await
+            }
+            Self::DeleteLabel(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, ctx, repository)).await
+            }
+            Self::CopyLabels(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, ctx, repository)).await
+            }
+            Self::CreateIssue(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, ctx, repository)).await
+            }
+            Self::CreateMilestone(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, ctx, repository)).await
+            }
+            Self::SetIssueMilestone(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, repository)).await
+            }
+            Self::CreateIssueFromTemplate(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, ctx, repository)).await
+            }
+            Self::CreateTeam(options) => vec![self.complete(
+                ctx,
+                None,
+                match ctx.job {
+                    None => Outcome::Skipped(NO_JOB.to_string()),
+                    Some(job) => with_retry(ctx, || options.run(octocrab, job)).await.into(),
+                },
+            )],
+            Self::AddTeamRepository(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, ctx, repository)).await
+            }
+            Self::SyncCodeownersTeam(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, repository)).await
+            }
+            Self::SyncTeamRepos(options) => vec![self.complete(
+                ctx,
+                None,
+                match ctx.job {
+                    None => Outcome::Skipped(NO_JOB.to_string()),
+                    Some(job) => with_retry(ctx, || options.run(octocrab, job)).await.into(),
+                },
+            )],
+            Self::CreateGist(options) => {
+                let result = with_retry(ctx, || options.run(octocrab, ctx)).await;
+                vec![self.complete(ctx, None, result.into())]
+            }
+            Self::UpdateGist(options) => {
+                let result = with_retry(ctx, || options.run(octocrab)).await;
+                vec![self.complete(ctx, None, result.into())]
+            }
+            Self::SetOrgDefaultPermission(options) => {
+                let result = with_retry(ctx, || options.run(octocrab)).await;
+                vec![self.complete(ctx, None, result.into())]
+            }
+            Self::CreateBranch(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, ctx, repository)).await
+            }
+            Self::EnableAutoMerge(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, ctx, repository)).await
+            }
+            Self::LinkRepositoryToProject(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, ctx, repository)).await
+            }
+            Self::UpdateBranchProtection(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, ctx, repository)).await
+            }
+            Self::SetRepositoryVisibility(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, ctx, repository)).await
+            }
+            Self::SetRepositoryInfo(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, repository)).await
+            }
+            Self::SetActionsPermissions(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, repository)).await
+            }
+            Self::SetSubscription(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, repository)).await
+            }
+            Self::CreateRuleset(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, ctx, repository)).await
+            }
+            Self::WaitForWorkflow(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, ctx, repository)).await
+            }
+            Self::CreateAutolink(options) => {
+                for_each_repository(ctx, |repository| options.run(octocrab, repository)).await
+            }
+        }
+    }
+
+    /// The final outcome of the command on a repository, reported to the `on_command_complete` hook.
+    /// A missing target counts as done when the command ignores the missing ones,
+    /// and so does an autolink whose key prefix is already used.
+    fn complete(
         &self,
-        octocrab: &Octocrab,
         ctx: &Context<'_>,
-    ) -> Vec<Result<Response, Error>> {
-        info!("run: {:?}", self);
+        repository: Option<&Repository>,
+        outcome: Outcome,
+    ) -> Outcome {
+        let outcome = match outcome {
+            Outcome::Failed(err) if err.is_not_found() && self.ignores_missing(ctx.settings) => {
+                Outcome::Skipped(NOT_FOUND.to_string())
+            }
+            Outcome::Failed(err)
+                if err.is_already_exists() && matches!(self, Self::CreateAutolink(_)) =>
+            {
+                Outcome::Skipped(AUTOLINK_EXISTS.to_string())
+            }
+            Outcome::Succeeded(Response::Preview(description)) => Outcome::DryRun(description),
+            outcome => outcome,
+        };
+        if let Some(hook) = &ctx.settings.on_command_complete {
+            let info = CommandInfo {
+                batch: ctx.batch.display_name(),
+                job: ctx.job.and_then(|job| job.name.as_deref()),
+                step: ctx.step.and_then(|step| step.name.as_deref()),
+                command: self,
+                repository,
+            };
+            hook.call(&info, &outcome);
+        }
+        outcome
+    }
+
+    /// Whether a missing target counts as already done rather than as a failure,
+    /// only for the commands removing things.
+    fn ignores_missing(&self, settings: &Settings) -> bool {
+        match self {
+            Self::DeleteLabel(options) => options.ignore_missing.unwrap_or(settings.ignore_missing),
+            _ => false,
+        }
+    }
+
+    /// Describe what the command would do, without sending any request.
+    fn dry_run(&self, ctx: &Context<'_>) -> Vec<Outcome> {
+        self.for_each_unit(ctx, |repository| {
+            Outcome::DryRun(match repository {
+                Some(repository) => format!(
+                    "{} on {}/{}",
+                    self.name(),
+                    repository.owner,
+                    repository.name
+                ),
+                None => self.name().to_string(),
+            })
+        })
+    }
+
+    /// Skip the command for the given reason, without sending any request.
+    pub fn skip(&self, ctx: &Context<'_>, reason: &str) -> Vec<Outcome> {
+        self.for_each_unit(ctx, |_| Outcome::Skipped(reason.to_string()))
+    }
+
+    /// One outcome per unit of the command on each repository of the job,
+    /// or a single one if the command doesn't run per repository.
+    fn for_each_unit(
+        &self,
+        ctx: &Context<'_>,
+        outcome: impl Fn(Option<&Repository>) -> Outcome,
+    ) -> Vec<Outcome> {
+        if !self.is_per_repository() {
+            return vec![self.complete(ctx, None, outcome(None))];
+        }
+        match ctx.repositories() {
+            None => vec![self.complete(ctx, None, Outcome::Skipped(NO_JOB.to_string()))],
+            Some(repositories) => repositories
+                .iter()
+                .flat_map(|repository| std::iter::repeat_n(repository, self.units_per_repository()))
+                .map(|repository| self.complete(ctx, Some(repository), outcome(Some(repository))))
+                .collect(),
+        }
+    }
+
+    /// Number of outcomes of the command on each repository, one per label of `create-label`.
+    pub fn units_per_repository(&self) -> usize {
+        match self {
+            Self::CreateLabel(labels) => labels.labels().len(),
+            _ => 1,
+        }
+    }
+
+    /// The repository of the outcome at `index` of the command run by `job`.
+    /// The outcomes of a repository follow each other, in the order of `on_repositories`.
+    pub fn repository_of<'a>(&self, job: &'a Job, index: usize) -> Option<&'a Repository> {
+        match self.is_per_repository() {
+            true => job
+                .on_repositories
+                .get(index / self.units_per_repository().max(1)),
+            false => None,
+        }
+    }
+
+    /// The command undoing what this one did, given its response.
+    /// `None` if the command can't be undone.
+    pub fn inverse(&self, response: &Response) -> Option<Command> {
+        match (self, response) {
+            (Self::CreateLabel(_), Response::CreateLabel(label)) => {
+                Some(Self::DeleteLabel(DeleteLabelOptions {
+                    name: label.name.clone(),
+                    ignore_missing: None,
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
         match self {
-            Self::CreateLabel(options) => options.run(octocrab, ctx).await,
-            Self::CreateIssue(options) => options.run(octocrab, ctx).await,
-            Self::CreateTeam(options) => options.run(octocrab, ctx).await,
-            Self::CreateGist(options) => options.run(octocrab, ctx).await,
+            Self::CreateLabel(labels) => labels.validate(),
+            Self::AddTeamRepository(options) => options.validate(),
+            Self::SyncTeamRepos(options) => options.validate(),
+            Self::SetOrgDefaultPermission(options) => options.validate(),
+            Self::CreateIssue(options) => options.validate(),
+            Self::SetIssueMilestone(options) => options.validate(),
+            Self::EnableAutoMerge(options) => options.validate(),
+            Self::SetRepositoryVisibility(options) => options.validate(),
+            Self::SetRepositoryInfo(options) => options.validate(),
+            Self::SetActionsPermissions(options) => options.validate(),
+            Self::SetSubscription(options) => options.validate(),
+            Self::CreateRuleset(options) => options.validate(),
+            Self::CreateAutolink(options) => options.validate(),
+            _ => Ok(()),
         }
     }
+
+    /// Whether the command can't be taken back once run, like exposing a repository,
+    /// so it must be confirmed before running the batch.
+    pub fn is_destructive(&self) -> bool {
+        match self {
+            Self::SetRepositoryVisibility(options) => options.visibility == "public",
+            _ => false,
+        }
+    }
+}
+
+/// What a command completed on, given to the `on_command_complete` hook with its outcome.
+#[derive(Debug)]
+pub struct CommandInfo<'a> {
+    pub batch: String,
+    pub job: Option<&'a str>,
+    pub step: Option<&'a str>,
+    pub command: &'a Command,
+    /// `None` for the commands not running per repository.
+    pub repository: Option<&'a Repository>,
+}
+
+/// Reason given when a command targeting repositories runs outside of a job.
+const NO_JOB: &str = "no job to target repositories";
+
+/// Reason given when the target of a deletion is already missing.
+const NOT_FOUND: &str = "not found";
+
+/// Reason given when Github rejects an autolink because its key prefix is already used.
+const AUTOLINK_EXISTS: &str = "key prefix already used";
+
+/// Run `statement` concurrently on each repository of the job, or the repository of the context.
+/// Results are stored by index, so they come in the order of `on_repositories`
+/// whatever the order the statements complete in.
+pub async fn for_each_repository<'a, F, Fut>(ctx: &Context<'a>, statement: F) -> Vec<Outcome>
+where
+    F: Fn(&'a Repository) -> Fut,
+    Fut: Future<Output = Result<Response, Error>>,
+{
+    for_each_repository_and(ctx, &[()], |repository, _| statement(repository)).await
+}
+
+/// Run `statement` concurrently on each repository of the job and each of `items`.
+/// Results come repository by repository, in the order of `on_repositories` then `items`.
+pub async fn for_each_repository_and<'a, T, F, Fut>(
+    ctx: &Context<'a>,
+    items: &'a [T],
+    statement: F,
+) -> Vec<Outcome>
+where
+    F: Fn(&'a Repository, &'a T) -> Fut,
+    Fut: Future<Output = Result<Response, Error>>,
+{
+    let complete = |repository, outcome| match ctx.command {
+        Some(command) => command.complete(ctx, repository, outcome),
+        None => outcome,
+    };
+    let repositories = match ctx.repositories() {
+        None => return vec![complete(None, Outcome::Skipped(NO_JOB.to_string()))],
+        Some(repositories) => repositories,
+    };
+
+    let statement = &statement;
+    let units: Vec<(&Repository, &T)> = repositories
+        .iter()
+        .flat_map(|repository| items.iter().map(move |item| (repository, item)))
+        .collect();
+    let mut statements: FuturesUnordered<_> = units
+        .iter()
+        .enumerate()
+        .map(|(index, &(repository, item))| async move {
+            (index, with_retry(ctx, || statement(repository, item)).await)
+        })
+        .collect();
+
+    let mut outcomes: Vec<Option<Outcome>> = std::iter::repeat_with(|| None)
+        .take(statements.len())
+        .collect();
+    while let Some((index, result)) = statements.next().await {
+        outcomes[index] = Some(complete(Some(units[index].0), result.into()));
+    }
+    outcomes.into_iter().flatten().collect()
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+/// Run `statement` with the retry policy of the settings, or the number of retries of the step,
+/// which also retries the idempotent commands on something missing that may show up meanwhile.
+/// Each attempt is bounded by the request timeout.
+async fn with_retry<F, Fut>(ctx: &Context<'_>, statement: F) -> Result<Response, Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<Response, Error>>,
+{
+    let idempotent = ctx.command.is_none_or(Command::is_idempotent);
+    let policy = match ctx.step.and_then(|step| step.retries) {
+        Some(retries) => Cow::Owned(RetryPolicy {
+            max_attempts: retries.saturating_add(1),
+            retry_missing: true,
+            ..ctx.settings.retry.clone()
+        }),
+        None => Cow::Borrowed(&ctx.settings.retry),
+    };
+    let request_timeout = ctx.settings.request_timeout;
+    policy
+        .retry(idempotent, || {
+            rest::with_request_timeout(request_timeout, statement())
+        })
+        .await
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct CreateGistOptions {
     pub title: String,
     pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub public: Option<bool>,
 }
 
 impl CreateGistOptions {
-    pub async fn run(
-        &self,
-        octocrab: &Octocrab,
-        _ctx: &Context<'_>,
-    ) -> Vec<Result<Response, Error>> {
-        let gist_res = octocrab
-            .gists()
-            .create()
-            .file(&self.title, &self.content)
-            .description(&self.description.clone().unwrap_or_default())
-            .public(self.public.unwrap_or(false))
-            .send()
-            .await;
+    pub async fn run(&self, octocrab: &Octocrab, ctx: &Context<'_>) -> Result<Response, Error> {
+        let description = self.description.as_deref().unwrap_or_default();
+        let gist = rest::timed(
+            octocrab
+                .gists()
+                .create()
+                .file(&self.title, &self.content)
+                .description(provenance::stamp(ctx, description))
+                .public(self.public.unwrap_or(false))
+                .send(),
+        )
+        .await?;
+        Ok(Response::CreateGist(gist))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GistFile {
+    pub name: String,
+    /// The new content of the file, an empty content deletes the file.
+    pub content: String,
+}
+
+/// Update the files and the description of an existing gist, once for the whole job.
+/// The files of the gist not listed are kept as they are.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct UpdateGistOptions {
+    pub gist_id: String,
+    pub files: Vec<GistFile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
 
-        match gist_res {
-            Ok(gist) => vec![Ok(Response::CreateGist(gist))],
-            Err(err) => vec![Err(Error::from(err))],
+impl UpdateGistOptions {
+    pub async fn run(&self, octocrab: &Octocrab) -> Result<Response, Error> {
+        // Github deletes the files set to null.
+        let files: serde_json::Map<String, serde_json::Value> = self
+            .files
+            .iter()
+            .map(|file| {
+                let value = match file.content.is_empty() {
+                    true => serde_json::Value::Null,
+                    false => serde_json::json!({ "content": file.content }),
+                };
+                (file.name.clone(), value)
+            })
+            .collect();
+        let mut body = serde_json::json!({ "files": files });
+        if let Some(description) = &self.description {
+            body["description"] = serde_json::json!(description);
         }
+        let route = format!("gists/{}", rest::segment(&self.gist_id));
+        let gist = rest::patch(octocrab, &route, &body).await?;
+        Ok(Response::UpdateGist(gist))
     }
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct CreateTeamOptions {
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     pub owner: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub maintainers: Option<Vec<String>>,
 }
 
 impl CreateTeamOptions {
+    pub async fn run(&self, octocrab: &Octocrab, job: &Job) -> Result<Response, Error> {
+        let on_repositories = &job.on_repositories;
+        let repo_names: &Vec<String> = &on_repositories
+            .iter()
+            .map(|repository| repository.name.clone())
+            .collect();
+
+        let description = self.description.clone().unwrap_or_default();
+        let maintainers = self.maintainers.clone().unwrap_or_default();
+
+        let team = rest::timed(
+            octocrab
+                .teams(&self.owner)
+                .create(&self.name)
+                .description(&description)
+                .maintainers(&maintainers)
+                .repo_names(repo_names)
+                .send(),
+        )
+        .await?;
+        Ok(Response::CreateTeam(team))
+    }
+}
+
+/// Permissions a team can be granted on a repository.
+const TEAM_PERMISSIONS: [&str; 5] = ["pull", "triage", "push", "maintain", "admin"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct AddTeamRepositoryOptions {
+    pub org: String,
+    pub team_slug: String,
+    /// One of `TEAM_PERMISSIONS`, defaults to the default permission of the team.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permission: Option<String>,
+}
+
+impl AddTeamRepositoryOptions {
+    pub fn validate(&self) -> Result<(), Error> {
+        match &self.permission {
+            Some(permission) if !TEAM_PERMISSIONS.contains(&permission.as_str()) => {
+                Err(Error::Validation(format!(
+                    "unknown team permission {:?}, expected one of {}",
+                    permission,
+                    TEAM_PERMISSIONS.join(", ")
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
     pub async fn run(
         &self,
         octocrab: &Octocrab,
-        ctx: &Context<'_>,
-    ) -> Vec<Result<Response, Error>> {
-        let team = match ctx.job {
-            None => Ok(Response::None),
-            Some(job) => {
-                let on_repositories = &job.on_repositories;
-                let repo_names: &Vec<String> = &on_repositories
-                    .iter()
-                    .map(|repository| repository.name.clone())
-                    .collect();
+        _ctx: &Context<'_>,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let route = format!(
+            "orgs/{}/teams/{}/repos/{}/{}",
+            self.org, self.team_slug, repository.owner, repository.name
+        );
+        let body = match &self.permission {
+            Some(permission) => serde_json::json!({ "permission": permission }),
+            None => serde_json::json!({}),
+        };
+        rest::put(octocrab, &route, &body).await?;
+        Ok(Response::AddTeamRepository(repository.clone()))
+    }
+}
+
+/// Make the repositories of a team exactly the repositories of the job, once for the whole job,
+/// with the given permission.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct SyncTeamReposOptions {
+    pub org: String,
+    pub team_slug: String,
+    /// One of `TEAM_PERMISSIONS`.
+    pub permission: String,
+    /// Remove the repositories of the team missing from the job, defaults to false.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prune: Option<bool>,
+}
 
-                let description = self.description.clone().unwrap_or_default();
-                let maintainers = self.maintainers.clone().unwrap_or_default();
+/// The `owner/name` of the repositories changed to match the job.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TeamReposSync {
+    pub added: Vec<String>,
+    /// Repositories of the team already, with another permission.
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl SyncTeamReposOptions {
+    pub fn validate(&self) -> Result<(), Error> {
+        if TEAM_PERMISSIONS.contains(&self.permission.as_str()) {
+            Ok(())
+        } else {
+            Err(Error::Validation(format!(
+                "unknown team permission {:?}, expected one of {}",
+                self.permission,
+                TEAM_PERMISSIONS.join(", ")
+            )))
+        }
+    }
 
-                let team_res = octocrab
-                    .teams(&self.owner)
-                    .create(&self.name)
-                    .description(&description)
-                    .maintainers(&maintainers)
-                    .repo_names(&repo_names)
-                    .send()
-                    .await;
+    pub async fn run(&self, octocrab: &Octocrab, job: &Job) -> Result<Response, Error> {
+        let route = format!("orgs/{}/teams/{}/repos", self.org, self.team_slug);
+        let page: Page<serde_json::Value> =
+            rest::get(octocrab, &route, Some(&[("per_page", "100")])).await?;
+        let current = rest::all_pages(octocrab, page).await?;
+        let full_name = |owner: &str, name: &str| format!("{}/{}", owner, name);
+        let current: Vec<(String, Option<&str>)> = current
+            .iter()
+            .map(|repository| {
+                let name = full_name(
+                    repository["owner"]["login"].as_str().unwrap_or_default(),
+                    repository["name"].as_str().unwrap_or_default(),
+                );
+                // The highest permission granted is the one of the team.
+                let permission = TEAM_PERMISSIONS.iter().rev().copied().find(|permission| {
+                    repository["permissions"][permission]
+                        .as_bool()
+                        .unwrap_or_default()
+                });
+                (name, permission)
+            })
+            .collect();
 
-                match team_res {
-                    Ok(team) => Ok(Response::CreateTeam(team)),
-                    Err(err) => Err(Error::from(err)),
+        let mut sync = TeamReposSync::default();
+        for repository in &job.on_repositories {
+            let name = full_name(&repository.owner, &repository.name);
+            let permission = current
+                .iter()
+                .find(|(known, _)| known.eq_ignore_ascii_case(&name))
+                .map(|(_, permission)| *permission);
+            if permission == Some(Some(self.permission.as_str())) {
+                continue;
+            }
+            rest::put(
+                octocrab,
+                &format!("{}/{}", route, name),
+                &serde_json::json!({ "permission": self.permission }),
+            )
+            .await?;
+            match permission {
+                None => sync.added.push(name),
+                Some(_) => sync.updated.push(name),
+            }
+        }
+        if self.prune.unwrap_or_default() {
+            for (name, _) in &current {
+                let listed = job.on_repositories.iter().any(|repository| {
+                    full_name(&repository.owner, &repository.name).eq_ignore_ascii_case(name)
+                });
+                if !listed {
+                    rest::delete(octocrab, &format!("{}/{}", route, name)).await?;
+                    sync.removed.push(name.clone());
                 }
             }
-        };
-        vec![team]
+        }
+        Ok(Response::SyncTeamRepos(sync))
+    }
+}
+
+/// Paths Github looks for the CODEOWNERS file of a repository at, in this order.
+const CODEOWNERS_PATHS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// Team permissions allowing to push to a repository, enough to review its pull requests.
+const WRITE_PERMISSIONS: [&str; 3] = ["push", "maintain", "admin"];
+
+/// Grant write permission to the team on each repository whose CODEOWNERS mentions it,
+/// so the reviews requested from the team can be given.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct SyncCodeownersTeamOptions {
+    pub team_slug: String,
+    pub org: String,
+}
+
+/// What the CODEOWNERS file of a repository says, and what was changed for the team.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CodeownersTeamSync {
+    /// Path of the CODEOWNERS file, `None` if the repository has none.
+    pub codeowners: Option<String>,
+    /// The `org/team` owners mentioned by the file, in the order they appear.
+    pub teams: Vec<String>,
+    /// Permission of the team on the repository before the sync, `None` if it had none.
+    pub previous_permission: Option<String>,
+    /// Whether write permission was granted, when the file mentions the team and it had less.
+    pub granted: bool,
+}
+
+impl SyncCodeownersTeamOptions {
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let mut sync = CodeownersTeamSync::default();
+        for path in CODEOWNERS_PATHS {
+            if let Some(content) = read_file(octocrab, repository, path).await? {
+                sync.codeowners = Some(path.to_string());
+                sync.teams = codeowners_teams(&content);
+                break;
+            }
+        }
+        let team = format!("{}/{}", self.org, self.team_slug);
+        if !sync
+            .teams
+            .iter()
+            .any(|owner| owner.eq_ignore_ascii_case(&team))
+        {
+            return Ok(Response::SyncCodeownersTeam(sync));
+        }
+
+        let route = format!("repos/{}/{}/teams", repository.owner, repository.name);
+        let page: Page<serde_json::Value> =
+            rest::get(octocrab, &route, Some(&[("per_page", "100")])).await?;
+        let teams = rest::all_pages(octocrab, page).await?;
+        sync.previous_permission = teams
+            .iter()
+            .find(|team| {
+                team["slug"]
+                    .as_str()
+                    .is_some_and(|slug| slug.eq_ignore_ascii_case(&self.team_slug))
+            })
+            .and_then(|team| team["permission"].as_str())
+            .map(ToString::to_string);
+        if sync
+            .previous_permission
+            .as_deref()
+            .is_some_and(|permission| WRITE_PERMISSIONS.contains(&permission))
+        {
+            return Ok(Response::SyncCodeownersTeam(sync));
+        }
+
+        let route = format!(
+            "orgs/{}/teams/{}/repos/{}/{}",
+            self.org, self.team_slug, repository.owner, repository.name
+        );
+        rest::put(
+            octocrab,
+            &route,
+            &serde_json::json!({ "permission": "push" }),
+        )
+        .await?;
+        sync.granted = true;
+        Ok(Response::SyncCodeownersTeam(sync))
+    }
+}
+
+/// The `org/team` owners of the rules of a CODEOWNERS file, without duplicates.
+/// The users and emails owning files aren't teams.
+fn codeowners_teams(codeowners: &str) -> Vec<String> {
+    let mut teams: Vec<String> = vec![];
+    for line in codeowners.lines() {
+        let rule = line.split('#').next().unwrap_or_default();
+        let owners = rule.split_whitespace().skip(1);
+        for team in owners.filter_map(|owner| owner.strip_prefix('@')) {
+            if team.contains('/') && !teams.iter().any(|known| known == team) {
+                teams.push(team.to_string());
+            }
+        }
+    }
+    teams
+}
+
+/// The content of the file at `path` of the default branch, `None` if it's missing.
+async fn read_file(
+    octocrab: &Octocrab,
+    repository: &Repository,
+    path: &str,
+) -> Result<Option<String>, Error> {
+    let path: Vec<String> = path.split('/').map(rest::segment).collect();
+    let route = format!(
+        "repos/{}/{}/contents/{}",
+        repository.owner,
+        repository.name,
+        path.join("/")
+    );
+    // An empty repository answers 404 as well, with its own message.
+    let contents = rest::get::<ContentItems>(octocrab, &route, None::<&()>).await;
+    match contents {
+        Err(err) if err.is_not_found() => Ok(None),
+        Err(err) => Err(err),
+        Ok(mut contents) => Ok(contents
+            .take_items()
+            .first()
+            .and_then(|content| content.decoded_content())),
+    }
+}
+
+/// Base permissions the members of an organization can have on its repositories.
+const ORG_PERMISSIONS: [&str; 4] = ["read", "write", "admin", "none"];
+
+/// Set the base permission of the members of an organization, once for the whole job.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct SetOrgDefaultPermissionOptions {
+    pub org: String,
+    /// One of `ORG_PERMISSIONS`.
+    pub default_repository_permission: String,
+}
+
+impl SetOrgDefaultPermissionOptions {
+    pub fn validate(&self) -> Result<(), Error> {
+        if ORG_PERMISSIONS.contains(&self.default_repository_permission.as_str()) {
+            Ok(())
+        } else {
+            Err(Error::Validation(format!(
+                "unknown default repository permission {:?}, expected one of {}",
+                self.default_repository_permission,
+                ORG_PERMISSIONS.join(", ")
+            )))
+        }
+    }
+
+    pub async fn run(&self, octocrab: &Octocrab) -> Result<Response, Error> {
+        let body = serde_json::json!({
+            "default_repository_permission": self.default_repository_permission,
+        });
+        let org = rest::patch(octocrab, &format!("orgs/{}", self.org), &body).await?;
+        Ok(Response::UpdateOrg(org))
     }
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
 pub struct CreateIssueOptions {
     pub title: String,
     pub body: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub milestone: Option<u64>,
+    /// Title of the milestone, resolved to its number in each repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub milestone_title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub assignees: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub labels: Option<Vec<String>>,
 }
 
@@ -119,73 +895,4104 @@ impl CreateIssueOptions {
         &self,
         octocrab: &Octocrab,
         ctx: &Context<'_>,
-    ) -> Vec<Result<Response, Error>> {
-        match ctx.job {
-            None => vec![Ok(Response::None)],
-            Some(job) => {
-                let on_repositories = &job.on_repositories;
-                let statements = on_repositories.iter().map(|repository| async move {
-                    let milestone = self.milestone.unwrap_or_default();
-                    let assignees = self.assignees.clone().unwrap_or_default();
-                    let labels = self.labels.clone().unwrap_or_default();
-
-                    let issue = octocrab
-                        .issues(&repository.owner, &repository.name)
-                        .create(&self.title)
-                        .body(&self.body)
-                        .milestone(milestone)
-                        .assignees(assignees)
-                        .labels(labels)
-                        .send()
-                        .await?;
-
-                    Ok(Response::CreateIssue(issue))
-                });
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        if ctx.settings.validate_assignees {
+            self.validate_assignees(octocrab, ctx, repository).await?;
+        }
+
+        let milestone = match &self.milestone_title {
+            Some(title) => resolve_milestone(octocrab, repository, title).await?,
+            None => self.milestone.unwrap_or_default(),
+        };
+        let assignees = self.assignees.clone().unwrap_or_default();
+        let labels = self.labels.clone().unwrap_or_default();
+
+        let issue = rest::timed(
+            octocrab
+                .issues(&repository.owner, &repository.name)
+                .create(&self.title)
+                .body(provenance::stamp(ctx, &self.body))
+                .milestone(milestone)
+                .assignees(assignees)
+                .labels(labels)
+                .send(),
+        )
+        .await?;
+
+        Ok(Response::CreateIssue(issue))
+    }
 
-                let issues: Vec<Result<Response, Error>> =
-                    futures::future::join_all(statements).await;
-                issues
+    pub fn validate(&self) -> Result<(), Error> {
+        match (&self.milestone, &self.milestone_title) {
+            (Some(_), Some(_)) => Err(Error::Validation(format!(
+                "issue {:?} has both a milestone and a milestone-title",
+                self.title
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    async fn validate_assignees(
+        &self,
+        octocrab: &Octocrab,
+        ctx: &Context<'_>,
+        repository: &Repository,
+    ) -> Result<(), Error> {
+        let mut invalid_assignees = vec![];
+        for assignee in self.assignees.iter().flatten() {
+            if !ctx
+                .cache
+                .is_assignable(octocrab, repository, assignee)
+                .await?
+            {
+                invalid_assignees.push(assignee.clone());
             }
         }
+        if invalid_assignees.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidAssignees {
+                repository: repository.clone(),
+                assignees: invalid_assignees,
+            })
+        }
     }
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
-pub struct CreateLabelOptions {
-    pub name: String,
-    pub color: String,
-    pub description: String,
+/// What `create-milestone` does when the repository already has a milestone with the same title.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IfExists {
+    /// Keep the existing milestone as it is.
+    #[default]
+    Skip,
+    /// Update the description and due date of the existing milestone.
+    Update,
+    Fail,
 }
 
-impl CreateLabelOptions {
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct CreateMilestoneOptions {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// ISO 8601 timestamp, like `2024-12-31T00:00:00Z`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub due_on: Option<String>,
+    #[serde(default)]
+    pub if_exists: IfExists,
+}
+
+impl CreateMilestoneOptions {
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        _ctx: &Context<'_>,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let route = format!("repos/{}/{}/milestones", repository.owner, repository.name);
+        let mut body = serde_json::json!({ "title": self.title });
+        if let Some(description) = &self.description {
+            body["description"] = description.clone().into();
+        }
+        if let Some(due_on) = &self.due_on {
+            body["due_on"] = due_on.clone().into();
+        }
+
+        let milestone = match find_milestone(octocrab, repository, &self.title).await? {
+            None => rest::post(octocrab, &route, &body).await?,
+            Some(existing) => match self.if_exists {
+                IfExists::Skip => existing,
+                IfExists::Update => {
+                    let route = format!("{}/{}", route, existing.number);
+                    rest::patch(octocrab, &route, &body).await?
+                }
+                IfExists::Fail => {
+                    return Err(Error::MilestoneExists {
+                        repository: repository.clone(),
+                        title: self.title.clone(),
+                    })
+                }
+            },
+        };
+        Ok(Response::CreateMilestone(milestone))
+    }
+}
+
+/// The milestone of the repository with the given title, open or closed.
+async fn find_milestone(
+    octocrab: &Octocrab,
+    repository: &Repository,
+    title: &str,
+) -> Result<Option<Milestone>, Error> {
+    let route = format!("repos/{}/{}/milestones", repository.owner, repository.name);
+    let parameters = [("state", "all"), ("per_page", "100")];
+    let page: Page<Milestone> = rest::get(octocrab, &route, Some(&parameters)).await?;
+    let milestones = rest::all_pages(octocrab, page).await?;
+    Ok(milestones
+        .into_iter()
+        .find(|milestone| milestone.title == title))
+}
+
+/// The number of the milestone of the repository with the given title, which must exist.
+async fn resolve_milestone(
+    octocrab: &Octocrab,
+    repository: &Repository,
+    title: &str,
+) -> Result<u64, Error> {
+    let milestone = find_milestone(octocrab, repository, title)
+        .await?
+        .ok_or_else(|| Error::MissingMilestone {
+            repository: repository.clone(),
+            title: title.to_string(),
+        })?;
+    Ok(milestone.number as u64)
+}
+
+/// Set the milestone of an existing issue, or clear it when neither
+/// `milestone` nor `milestone-title` is given.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct SetIssueMilestoneOptions {
+    /// Number of the issue.
+    pub number: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub milestone: Option<u64>,
+    /// Title of the milestone, resolved to its number in each repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub milestone_title: Option<String>,
+}
+
+impl SetIssueMilestoneOptions {
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let milestone = match &self.milestone_title {
+            Some(title) => Some(resolve_milestone(octocrab, repository, title).await?),
+            None => self.milestone,
+        };
+        let route = format!(
+            "repos/{}/{}/issues/{}",
+            repository.owner, repository.name, self.number
+        );
+        let body = serde_json::json!({ "milestone": milestone });
+        let issue = rest::patch(octocrab, &route, &body).await?;
+        Ok(Response::UpdateIssue(issue))
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        match (&self.milestone, &self.milestone_title) {
+            (Some(_), Some(_)) => Err(Error::Validation(format!(
+                "issue #{} has both a milestone and a milestone-title",
+                self.number
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Directory of the issue templates of a repository.
+const ISSUE_TEMPLATE_DIR: &str = ".github/ISSUE_TEMPLATE";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CreateIssueFromTemplateOptions {
+    /// File name of the template in `.github/ISSUE_TEMPLATE`.
+    pub template: String,
+    pub title: String,
+    /// Values of the `{{ field }}` placeholders of the title and the template.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<HashMap<String, String>>,
+}
+
+impl CreateIssueFromTemplateOptions {
     pub async fn run(
         &self,
         octocrab: &Octocrab,
         ctx: &Context<'_>,
-    ) -> Vec<Result<Response, Error>> {
-        match ctx.job {
-            None => vec![Ok(Response::None)],
-            Some(job) => {
-                let on_repositories = &job.on_repositories;
-                let statements = on_repositories.iter().map(|repository| async move {
-                    let label = octocrab
-                        .issues(&repository.owner, &repository.name)
-                        .create_label(&self.name, &self.color, &self.description)
-                        .await?;
-                    Ok(Response::CreateLabel(label))
-                });
-                let labels: Vec<Result<Response, Error>> =
-                    futures::future::join_all(statements).await;
-                labels
-            }
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let template = self.read_template(octocrab, repository).await?;
+        let issue = CreateIssueOptions {
+            title: self.fill(&self.title),
+            body: self.fill(strip_front_matter(&template)),
+            milestone: None,
+            milestone_title: None,
+            assignees: None,
+            labels: None,
+        };
+        issue.run(octocrab, ctx, repository).await
+    }
+
+    async fn read_template(
+        &self,
+        octocrab: &Octocrab,
+        repository: &Repository,
+    ) -> Result<String, Error> {
+        let path = format!("{}/{}", ISSUE_TEMPLATE_DIR, self.template);
+        read_file(octocrab, repository, &path)
+            .await?
+            .ok_or_else(|| Error::MissingTemplate {
+                repository: repository.clone(),
+                template: self.template.clone(),
+            })
+    }
+
+    fn fill(&self, text: &str) -> String {
+        let mut filled = text.to_string();
+        for (name, value) in self.fields.iter().flatten() {
+            filled = filled
+                .replace(&format!("{{{{ {} }}}}", name), value)
+                .replace(&format!("{{{{{}}}}}", name), value);
         }
+        filled
     }
 }
 
-pub enum Response {
-    CreateLabel(Label),
-    CreateIssue(Issue),
-    CreateTeam(Team),
-    CreateGist(Gist),
-    None,
+/// The template without its YAML front matter, holding the metadata of the template.
+fn strip_front_matter(template: &str) -> &str {
+    template
+        .strip_prefix("---\n")
+        .and_then(|rest| rest.split_once("\n---\n"))
+        .map(|(_, body)| body)
+        .unwrap_or(template)
+}
+
+/// The label or the list of labels of a `create-label` command.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CreateLabels {
+    One(CreateLabelOptions),
+    Many(Vec<CreateLabelOptions>),
+}
+
+impl CreateLabels {
+    pub fn labels(&self) -> &[CreateLabelOptions] {
+        match self {
+            Self::One(options) => std::slice::from_ref(options),
+            Self::Many(labels) => labels,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.labels().is_empty() {
+            return Err(Error::Validation(
+                "create-label has an empty list of labels".to_string(),
+            ));
+        }
+        self.labels()
+            .iter()
+            .try_for_each(|options| palette::resolve(&options.name, &options.color).map(|_| ()))
+    }
+}
+
+impl From<CreateLabelOptions> for CreateLabels {
+    fn from(options: CreateLabelOptions) -> Self {
+        Self::One(options)
+    }
+}
+
+/// A single label as a mapping, or a list of them as a sequence.
+/// Unlike `#[serde(untagged)]`, the errors point at what's wrong in the label.
+impl<'de> Deserialize<'de> for CreateLabels {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = CreateLabels;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a label or a list of labels")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> Result<Self::Value, A::Error> {
+                CreateLabelOptions::deserialize(MapAccessDeserializer::new(map))
+                    .map(CreateLabels::One)
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                Vec::deserialize(SeqAccessDeserializer::new(seq)).map(CreateLabels::Many)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CreateLabelOptions {
+    pub name: String,
+    pub color: String,
+    pub description: String,
+}
+
+impl CreateLabelOptions {
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        _ctx: &Context<'_>,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let color = palette::resolve(&self.name, &self.color)?;
+        let label = rest::timed(
+            octocrab
+                .issues(&repository.owner, &repository.name)
+                .create_label(&self.name, &color, &self.description),
+        )
+        .await?;
+        Ok(Response::CreateLabel(label))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct DeleteLabelOptions {
+    pub name: String,
+    /// Skip the label when it's already missing, defaults to `--ignore-missing`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ignore_missing: Option<bool>,
+}
+
+impl DeleteLabelOptions {
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        _ctx: &Context<'_>,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let route = format!(
+            "repos/{}/{}/labels/{}",
+            repository.owner,
+            repository.name,
+            rest::segment(&self.name)
+        );
+        rest::delete(octocrab, &route).await?;
+        Ok(Response::DeleteLabel(self.name.clone()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct CopyLabelsOptions {
+    pub from_owner: String,
+    pub from_repo: String,
+    /// Delete the labels missing from the source repository, defaults to false.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prune: Option<bool>,
+}
+
+/// Names of the labels changed to match the source repository.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LabelChanges {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub pruned: Vec<String>,
+}
+
+/// The changes making a repository match the source repository, and those actually made.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LabelSync {
+    pub planned: LabelChanges,
+    pub applied: LabelChanges,
+}
+
+/// The labels to change in a repository to match the source repository.
+struct LabelPlan {
+    create: Vec<Label>,
+    /// Current name of the label in the repository, and the source label to match.
+    update: Vec<(String, Label)>,
+    prune: Vec<String>,
+}
+
+impl LabelPlan {
+    fn changes(&self) -> LabelChanges {
+        LabelChanges {
+            created: self.create.iter().map(|label| label.name.clone()).collect(),
+            updated: self
+                .update
+                .iter()
+                .map(|(_, label)| label.name.clone())
+                .collect(),
+            pruned: self.prune.clone(),
+        }
+    }
+}
+
+impl CopyLabelsOptions {
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        ctx: &Context<'_>,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let plan = self.plan(octocrab, ctx, repository).await?;
+        let route = |name: &str| {
+            format!(
+                "repos/{}/{}/labels/{}",
+                repository.owner,
+                repository.name,
+                rest::segment(name)
+            )
+        };
+
+        let issues = octocrab.issues(&repository.owner, &repository.name);
+        let mut applied = LabelChanges::default();
+        for label in &plan.create {
+            let description = label.description.clone().unwrap_or_default();
+            rest::timed(issues.create_label(&label.name, &label.color, &description)).await?;
+            applied.created.push(label.name.clone());
+        }
+        for (name, label) in &plan.update {
+            let body = serde_json::json!({
+                "new_name": label.name,
+                "color": label.color,
+                "description": label.description.clone().unwrap_or_default(),
+            });
+            let _: Label = rest::patch(octocrab, &route(name), &body).await?;
+            applied.updated.push(label.name.clone());
+        }
+        for name in &plan.prune {
+            rest::delete(octocrab, &route(name)).await?;
+            applied.pruned.push(name.clone());
+        }
+        Ok(Response::SyncLabels(LabelSync {
+            planned: plan.changes(),
+            applied,
+        }))
+    }
+
+    /// Describe the changes without applying them, for the dry-run mode.
+    pub async fn preview(
+        &self,
+        octocrab: &Octocrab,
+        ctx: &Context<'_>,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let planned = self.plan(octocrab, ctx, repository).await?.changes();
+        Ok(Response::Preview(format!(
+            "copy-labels on {}/{} would create {:?}, update {:?}, prune {:?}",
+            repository.owner, repository.name, planned.created, planned.updated, planned.pruned
+        )))
+    }
+
+    async fn plan(
+        &self,
+        octocrab: &Octocrab,
+        ctx: &Context<'_>,
+        repository: &Repository,
+    ) -> Result<LabelPlan, Error> {
+        let source = Repository {
+            owner: self.from_owner.clone(),
+            name: self.from_repo.clone(),
+        };
+        let source_labels = ctx.cache.labels(octocrab, &source).await?;
+        let mut labels: HashMap<String, Label> = list_labels(octocrab, repository)
+            .await?
+            .into_iter()
+            .map(|label| (label.name.to_lowercase(), label))
+            .collect();
+
+        let mut plan = LabelPlan {
+            create: vec![],
+            update: vec![],
+            prune: vec![],
+        };
+        for source_label in source_labels {
+            let description = source_label.description.clone().unwrap_or_default();
+            match labels.remove(&source_label.name.to_lowercase()) {
+                None => plan.create.push(source_label),
+                Some(label)
+                    if label.name != source_label.name
+                        || label.color != source_label.color
+                        || label.description.clone().unwrap_or_default() != description =>
+                {
+                    plan.update.push((label.name, source_label))
+                }
+                Some(_) => {}
+            }
+        }
+
+        if self.prune.unwrap_or(false) {
+            plan.prune = labels.into_values().map(|label| label.name).collect();
+            plan.prune.sort();
+        }
+        Ok(plan)
+    }
+}
+
+/// Every label of the repository, across all the pages.
+pub async fn list_labels(
+    octocrab: &Octocrab,
+    repository: &Repository,
+) -> Result<Vec<Label>, Error> {
+    let page = rest::timed(
+        octocrab
+            .issues(&repository.owner, &repository.name)
+            .list_labels_for_repo()
+            .per_page(100)
+            .send(),
+    )
+    .await?;
+    rest::all_pages(octocrab, page).await
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct CreateBranchOptions {
+    pub name: String,
+    /// The branch, `tags/<tag>` or commit sha to start from, defaults to the default branch
+    /// of each repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from_ref: Option<String>,
+}
+
+/// Reads `from-ref` as a tag when prefixed with `tags/` or `refs/tags/`, as a commit when
+/// it is a full sha, and as a branch otherwise.
+fn parse_reference(from_ref: &str) -> Reference {
+    let from_ref = from_ref.strip_prefix("refs/").unwrap_or(from_ref);
+    if let Some(tag) = from_ref.strip_prefix("tags/") {
+        Reference::Tag(tag.to_string())
+    } else if from_ref.len() == 40 && from_ref.chars().all(|c| c.is_ascii_hexdigit()) {
+        Reference::Commit(from_ref.to_string())
+    } else {
+        Reference::Branch(
+            from_ref
+                .strip_prefix("heads/")
+                .unwrap_or(from_ref)
+                .to_string(),
+        )
+    }
+}
+
+impl CreateBranchOptions {
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        ctx: &Context<'_>,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let from_ref = match &self.from_ref {
+            Some(from_ref) => from_ref.clone(),
+            None => ctx
+                .cache
+                .repository(octocrab, repository)
+                .await?
+                .default_branch
+                .ok_or_else(|| Error::MissingDefaultBranch(repository.clone()))?,
+        };
+
+        let repos = octocrab.repos(&repository.owner, &repository.name);
+        let sha = match parse_reference(&from_ref) {
+            Reference::Commit(sha) => sha,
+            reference => match rest::timed(repos.get_ref(&reference)).await?.object {
+                Object::Commit { sha, .. } => sha,
+                // An annotated tag points to a tag object, itself pointing to the commit.
+                Object::Tag { sha, .. } => {
+                    let route = format!(
+                        "repos/{}/{}/git/tags/{}",
+                        repository.owner, repository.name, sha
+                    );
+                    let tag: serde_json::Value = rest::get(octocrab, &route, None::<&()>).await?;
+                    match tag["object"]["sha"].as_str() {
+                        Some(sha) if tag["object"]["type"] == "commit" => sha.to_string(),
+                        _ => return Err(Error::UnsupportedReference(from_ref)),
+                    }
+                }
+                _ => return Err(Error::UnsupportedReference(from_ref)),
+            },
+        };
+        let branch =
+            rest::timed(repos.create_ref(&Reference::Branch(self.name.clone()), sha)).await?;
+        Ok(Response::CreateBranch(branch))
+    }
+}
+
+/// Merge methods accepted by `enablePullRequestAutoMerge`.
+const MERGE_METHODS: [&str; 3] = ["merge", "squash", "rebase"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EnableAutoMergeOptions {
+    pub number: u64,
+    /// One of `merge`, `squash` or `rebase`, defaults to `merge`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+}
+
+impl EnableAutoMergeOptions {
+    pub fn validate(&self) -> Result<(), Error> {
+        match &self.method {
+            Some(method) if !MERGE_METHODS.contains(&method.to_lowercase().as_str()) => {
+                Err(Error::Validation(format!(
+                    "unknown merge method {:?}, expected one of {}",
+                    method,
+                    MERGE_METHODS.join(", ")
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        _ctx: &Context<'_>,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let method = self
+            .method
+            .clone()
+            .unwrap_or_else(|| "merge".to_string())
+            .to_uppercase();
+
+        let data = graphql::query(
+            octocrab,
+            "query($owner: String!, $name: String!, $number: Int!) {
+                repository(owner: $owner, name: $name) {
+                    pullRequest(number: $number) { id }
+                }
+            }",
+            serde_json::json!({
+                "owner": repository.owner,
+                "name": repository.name,
+                "number": self.number,
+            }),
+        )
+        .await?;
+        let pull_request_id = match &data["repository"]["pullRequest"]["id"] {
+            serde_json::Value::Null => {
+                return Err(Error::MissingPullRequest {
+                    repository: repository.clone(),
+                    number: self.number,
+                })
+            }
+            pull_request_id => pull_request_id.clone(),
+        };
+
+        let data = graphql::query(
+            octocrab,
+            "mutation($pullRequestId: ID!, $mergeMethod: PullRequestMergeMethod!) {
+                enablePullRequestAutoMerge(input: {
+                    pullRequestId: $pullRequestId,
+                    mergeMethod: $mergeMethod
+                }) {
+                    pullRequest { number autoMergeRequest { enabledAt mergeMethod } }
+                }
+            }",
+            serde_json::json!({
+                "pullRequestId": pull_request_id,
+                "mergeMethod": method,
+            }),
+        )
+        .await?;
+        Ok(Response::EnableAutoMerge(
+            data["enablePullRequestAutoMerge"]["pullRequest"].clone(),
+        ))
+    }
+}
+
+/// Link each repository to a project, which then lists it among its repositories.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct LinkRepositoryToProjectOptions {
+    /// Node id of the project, like `PVT_kwDOA`.
+    pub project_id: String,
+}
+
+impl LinkRepositoryToProjectOptions {
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        ctx: &Context<'_>,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let repository_id = ctx
+            .cache
+            .repository(octocrab, repository)
+            .await?
+            .node_id
+            .ok_or_else(|| Error::MissingNodeId(repository.clone()))?;
+
+        let data = graphql::query(
+            octocrab,
+            "mutation($projectId: ID!, $repositoryId: ID!) {
+                linkProjectV2ToRepository(input: {
+                    projectId: $projectId,
+                    repositoryId: $repositoryId
+                }) {
+                    repository { id nameWithOwner }
+                }
+            }",
+            serde_json::json!({
+                "projectId": self.project_id,
+                "repositoryId": repository_id,
+            }),
+        )
+        .await?;
+        Ok(Response::LinkRepositoryToProject(
+            data["linkProjectV2ToRepository"]["repository"].clone(),
+        ))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct UpdateBranchProtectionOptions {
+    pub branch: String,
+    /// Names of the required status checks, replacing the current ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required_checks: Option<Vec<String>>,
+    /// Require the branches to be up to date before merging.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enforce_admins: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required_approving_review_count: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dismiss_stale_reviews: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub require_code_owner_reviews: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required_linear_history: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_force_pushes: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_deletions: Option<bool>,
+}
+
+impl UpdateBranchProtectionOptions {
+    /// Read the current protection of the branch, apply the given fields on top of it
+    /// and write it back, so the settings left out of the batch are kept as they are.
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        _ctx: &Context<'_>,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let route = format!(
+            "repos/{}/{}/branches/{}/protection",
+            repository.owner, repository.name, self.branch
+        );
+        let current = rest::get(octocrab, &route, None::<&()>).await;
+        let current = match current {
+            Ok(current) => current,
+            // The branch isn't protected yet.
+            Err(err) if err.is_not_found() => serde_json::Value::Null,
+            Err(err) => return Err(err),
+        };
+        let mut protection = protection_request(&current);
+        self.merge_into(&mut protection);
+        let protection = rest::put_json(octocrab, &route, &protection).await?;
+        Ok(Response::ProtectBranch(protection))
+    }
+
+    fn merge_into(&self, protection: &mut serde_json::Value) {
+        if self.required_checks.is_some() || self.strict.is_some() {
+            let checks = &mut protection["required_status_checks"];
+            if checks.is_null() {
+                *checks = serde_json::json!({ "strict": false, "contexts": [] });
+            }
+            if let Some(required_checks) = &self.required_checks {
+                // The checks already required keep the app they must come from.
+                let apps: HashMap<String, serde_json::Value> = checks["checks"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|check| {
+                        Some((
+                            check["context"].as_str()?.to_string(),
+                            check["app_id"].clone(),
+                        ))
+                    })
+                    .collect();
+                checks["checks"] = required_checks
+                    .iter()
+                    .map(|context| match apps.get(context) {
+                        Some(app_id) if !app_id.is_null() => {
+                            serde_json::json!({ "context": context, "app_id": app_id })
+                        }
+                        _ => serde_json::json!({ "context": context }),
+                    })
+                    .collect();
+                checks["contexts"] = serde_json::json!(required_checks);
+            }
+            if let Some(strict) = self.strict {
+                checks["strict"] = strict.into();
+            }
+        }
+        if let Some(enforce_admins) = self.enforce_admins {
+            protection["enforce_admins"] = enforce_admins.into();
+        }
+
+        let review_fields = [
+            (
+                "required_approving_review_count",
+                self.required_approving_review_count.map(Into::into),
+            ),
+            (
+                "dismiss_stale_reviews",
+                self.dismiss_stale_reviews.map(Into::into),
+            ),
+            (
+                "require_code_owner_reviews",
+                self.require_code_owner_reviews.map(Into::into),
+            ),
+        ];
+        for (field, value) in review_fields {
+            if let Some(value) = value {
+                let reviews = &mut protection["required_pull_request_reviews"];
+                if reviews.is_null() {
+                    *reviews = serde_json::json!({});
+                }
+                reviews[field] = value;
+            }
+        }
+
+        let toggles = [
+            ("required_linear_history", self.required_linear_history),
+            ("allow_force_pushes", self.allow_force_pushes),
+            ("allow_deletions", self.allow_deletions),
+        ];
+        for (field, value) in toggles {
+            if let Some(value) = value {
+                protection[field] = value.into();
+            }
+        }
+    }
+}
+
+/// The body of the PUT request leaving the protection as it is, given the response
+/// of the GET request, `null` for an unprotected branch.
+/// Github reads and writes protections in different shapes.
+fn protection_request(current: &serde_json::Value) -> serde_json::Value {
+    let logins = |users: &serde_json::Value| -> Vec<serde_json::Value> {
+        users
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|user| user["login"].clone())
+            .collect()
+    };
+    let slugs = |items: &serde_json::Value| -> Vec<serde_json::Value> {
+        items
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|item| item["slug"].clone())
+            .collect()
+    };
+
+    let checks = &current["required_status_checks"];
+    let required_status_checks = match checks.is_null() {
+        true => serde_json::Value::Null,
+        false => {
+            let mut request = serde_json::json!({
+                "strict": checks["strict"].as_bool().unwrap_or(false),
+                "contexts": checks["contexts"].as_array().cloned().unwrap_or_default(),
+            });
+            if let Some(required) = checks["checks"].as_array() {
+                request["checks"] = required
+                    .iter()
+                    .map(|check| match check["app_id"].is_null() {
+                        true => serde_json::json!({ "context": check["context"] }),
+                        false => serde_json::json!({
+                            "context": check["context"],
+                            "app_id": check["app_id"],
+                        }),
+                    })
+                    .collect();
+            }
+            request
+        }
+    };
+
+    let reviews = &current["required_pull_request_reviews"];
+    let required_pull_request_reviews = match reviews.is_null() {
+        true => serde_json::Value::Null,
+        false => {
+            let mut request = serde_json::json!({
+                "dismiss_stale_reviews": reviews["dismiss_stale_reviews"],
+                "require_code_owner_reviews": reviews["require_code_owner_reviews"],
+                "required_approving_review_count": reviews["required_approving_review_count"],
+                "require_last_push_approval": reviews["require_last_push_approval"]
+                    .as_bool()
+                    .unwrap_or(false),
+            });
+            let dismissal = &reviews["dismissal_restrictions"];
+            if !dismissal.is_null() {
+                request["dismissal_restrictions"] = serde_json::json!({
+                    "users": logins(&dismissal["users"]),
+                    "teams": slugs(&dismissal["teams"]),
+                });
+            }
+            let bypass = &reviews["bypass_pull_request_allowances"];
+            if !bypass.is_null() {
+                request["bypass_pull_request_allowances"] = serde_json::json!({
+                    "users": logins(&bypass["users"]),
+                    "teams": slugs(&bypass["teams"]),
+                    "apps": slugs(&bypass["apps"]),
+                });
+            }
+            request
+        }
+    };
+
+    let restrictions = &current["restrictions"];
+    let restrictions = match restrictions.is_null() {
+        true => serde_json::Value::Null,
+        false => serde_json::json!({
+            "users": logins(&restrictions["users"]),
+            "teams": slugs(&restrictions["teams"]),
+            "apps": slugs(&restrictions["apps"]),
+        }),
+    };
+
+    let enabled = |field: &str| current[field]["enabled"].as_bool().unwrap_or(false);
+    serde_json::json!({
+        "required_status_checks": required_status_checks,
+        "enforce_admins": enabled("enforce_admins"),
+        "required_pull_request_reviews": required_pull_request_reviews,
+        "restrictions": restrictions,
+        "required_linear_history": enabled("required_linear_history"),
+        "allow_force_pushes": enabled("allow_force_pushes"),
+        "allow_deletions": enabled("allow_deletions"),
+        "required_conversation_resolution": enabled("required_conversation_resolution"),
+        "lock_branch": enabled("lock_branch"),
+        "block_creations": enabled("block_creations"),
+        "allow_fork_syncing": enabled("allow_fork_syncing"),
+    })
+}
+
+/// Visibilities of a repository, `internal` is only available to organizations
+/// of Github Enterprise.
+const VISIBILITIES: [&str; 3] = ["public", "private", "internal"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SetRepositoryVisibilityOptions {
+    /// One of `VISIBILITIES`.
+    pub visibility: String,
+}
+
+impl SetRepositoryVisibilityOptions {
+    pub fn validate(&self) -> Result<(), Error> {
+        match VISIBILITIES.contains(&self.visibility.as_str()) {
+            true => Ok(()),
+            false => Err(Error::Validation(format!(
+                "unknown repository visibility {:?}, expected one of {}",
+                self.visibility,
+                VISIBILITIES.join(", ")
+            ))),
+        }
+    }
+
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        ctx: &Context<'_>,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let metadata = ctx.cache.repository(octocrab, repository).await?;
+        if metadata.visibility.as_deref() == Some(self.visibility.as_str()) {
+            return Ok(Response::UpdateRepositorySettings(metadata));
+        }
+
+        let route = format!("repos/{}/{}", repository.owner, repository.name);
+        // The `private` boolean can't tell internal repositories apart, `visibility` can.
+        let metadata: octocrab::models::Repository = rest::patch(
+            octocrab,
+            &route,
+            &serde_json::json!({ "visibility": self.visibility }),
+        )
+        .await?;
+        ctx.cache.update_repository(repository, metadata.clone());
+        Ok(Response::UpdateRepositorySettings(metadata))
+    }
+}
+
+/// The fields left out are kept as they are, an empty string clears the field.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SetRepositoryInfoOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+}
+
+impl SetRepositoryInfoOptions {
+    pub fn validate(&self) -> Result<(), Error> {
+        match self.description.is_none() && self.homepage.is_none() {
+            true => Err(Error::Validation(
+                "set-repository-info needs a description or a homepage".to_string(),
+            )),
+            false => Ok(()),
+        }
+    }
+
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let route = format!("repos/{}/{}", repository.owner, repository.name);
+        // Only the given fields are sent, so the ones left out aren't reset by Github.
+        let repository = rest::patch(octocrab, &route, self).await?;
+        Ok(Response::UpdateRepositorySettings(repository))
+    }
+}
+
+/// Actions a repository can run: every one, the ones of its owner, or a selection of them.
+const ALLOWED_ACTIONS: [&str; 3] = ["all", "local_only", "selected"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct SetActionsPermissionsOptions {
+    pub enabled: bool,
+    /// One of `ALLOWED_ACTIONS`, only when Actions are enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_actions: Option<String>,
+}
+
+impl SetActionsPermissionsOptions {
+    pub fn validate(&self) -> Result<(), Error> {
+        match &self.allowed_actions {
+            Some(allowed) if !ALLOWED_ACTIONS.contains(&allowed.as_str()) => {
+                Err(Error::Validation(format!(
+                    "unknown allowed actions {:?}, expected one of {}",
+                    allowed,
+                    ALLOWED_ACTIONS.join(", ")
+                )))
+            }
+            Some(_) if !self.enabled => Err(Error::Validation(
+                "allowed-actions can't be set when Actions are disabled".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let route = format!(
+            "repos/{}/{}/actions/permissions",
+            repository.owner, repository.name
+        );
+        let mut body = serde_json::json!({ "enabled": self.enabled });
+        if let Some(allowed) = &self.allowed_actions {
+            body["allowed_actions"] = serde_json::json!(allowed);
+        }
+        rest::put(octocrab, &route, &body).await?;
+        Ok(Response::SetActionsPermissions(repository.clone()))
+    }
+}
+
+/// Watch a repository, or ignore its notifications, as the authenticated user.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SetSubscriptionOptions {
+    /// Receive the notifications of the repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscribed: Option<bool>,
+    /// Block the notifications of the repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ignored: Option<bool>,
+}
+
+impl SetSubscriptionOptions {
+    pub fn validate(&self) -> Result<(), Error> {
+        match (self.subscribed, self.ignored) {
+            (Some(true), Some(true)) => Err(Error::Validation(
+                "a subscription can't be both subscribed and ignored".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let route = format!(
+            "repos/{}/{}/subscription",
+            repository.owner, repository.name
+        );
+        let subscription = rest::put_json(octocrab, &route, self).await?;
+        Ok(Response::SetSubscription(subscription))
+    }
+}
+
+/// Targets of a ruleset.
+const RULESET_TARGETS: [&str; 3] = ["branch", "tag", "push"];
+
+/// Enforcements of a ruleset, `evaluate` only reports what the rules would block.
+const RULESET_ENFORCEMENTS: [&str; 3] = ["disabled", "active", "evaluate"];
+
+/// Types of rules only making sense for the rulesets targeting branches.
+const BRANCH_RULES: [&str; 5] = [
+    "branch_name_pattern",
+    "pull_request",
+    "required_status_checks",
+    "required_deployments",
+    "merge_queue",
+];
+
+/// Types of rules only making sense for the rulesets targeting tags.
+const TAG_RULES: [&str; 1] = ["tag_name_pattern"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CreateRulesetOptions {
+    pub name: String,
+    /// One of `RULESET_TARGETS`.
+    pub target: String,
+    /// One of `RULESET_ENFORCEMENTS`.
+    pub enforcement: String,
+    /// The rules, as documented by the Github API.
+    pub rules: serde_json::Value,
+    /// The refs the ruleset applies to, as documented by the Github API.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conditions: Option<serde_json::Value>,
+}
+
+impl CreateRulesetOptions {
+    pub fn validate(&self) -> Result<(), Error> {
+        let check = |kind: &str, value: &str, expected: &[&str]| match expected.contains(&value) {
+            true => Ok(()),
+            false => Err(Error::Validation(format!(
+                "unknown ruleset {} {:?}, expected one of {}",
+                kind,
+                value,
+                expected.join(", ")
+            ))),
+        };
+        check("target", &self.target, &RULESET_TARGETS)?;
+        check("enforcement", &self.enforcement, &RULESET_ENFORCEMENTS)?;
+        let rules = self.rules.as_array().ok_or_else(|| {
+            Error::Validation(format!(
+                "the rules of ruleset {:?} must be a list",
+                self.name
+            ))
+        })?;
+        // The other rule types are passed through as they are, Github knows best what they mean.
+        let unsupported: &[&str] = match self.target.as_str() {
+            "branch" => &TAG_RULES,
+            "tag" => &BRANCH_RULES,
+            _ => &[],
+        };
+        match rules
+            .iter()
+            .filter_map(|rule| rule["type"].as_str())
+            .find(|kind| unsupported.contains(kind))
+        {
+            Some(kind) => Err(Error::Validation(format!(
+                "ruleset {:?} targets {} refs, which can't have a {} rule",
+                self.name, self.target, kind
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Create the ruleset, or update the ruleset of the repository with the same name.
+    /// The rulesets of the organization apply to the repository too, but can't be updated there.
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        _ctx: &Context<'_>,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let route = format!("repos/{}/{}/rulesets", repository.owner, repository.name);
+        let mut body = serde_json::json!({
+            "name": self.name,
+            "target": self.target,
+            "enforcement": self.enforcement,
+            "rules": self.rules,
+        });
+        if let Some(conditions) = &self.conditions {
+            body["conditions"] = conditions.clone();
+        }
+
+        let parameters = [("includes_parents", "false"), ("per_page", "100")];
+        let page: Page<serde_json::Value> = rest::get(octocrab, &route, Some(&parameters)).await?;
+        let rulesets = rest::all_pages(octocrab, page).await?;
+        let existing = rulesets
+            .iter()
+            .find(|ruleset| {
+                ruleset["source_type"] == "Repository" && ruleset["name"] == self.name.as_str()
+            })
+            .and_then(|ruleset| ruleset["id"].as_u64());
+        let ruleset = match existing {
+            Some(id) => rest::put_json(octocrab, &format!("{}/{}", route, id), &body).await?,
+            None => rest::post(octocrab, &route, &body).await?,
+        };
+        Ok(Response::CreateRuleset(ruleset))
+    }
+}
+
+/// Time given to a workflow run to complete, unless `timeout-secs` is set.
+const WORKFLOW_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Wait for the last run of a workflow on a branch to complete, like one just dispatched.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct WaitForWorkflowOptions {
+    /// File name or id of the workflow, like `ci.yml`.
+    pub workflow: String,
+    pub git_ref: String,
+    /// Defaults to `WORKFLOW_TIMEOUT`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+}
+
+impl WaitForWorkflowOptions {
+    /// Check the last run every `poll_interval` of the settings, until it's completed.
+    /// The run may not be there yet right after it's dispatched, so a run completed already
+    /// only counts if it was created since the command started, or came after the first one seen.
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        ctx: &Context<'_>,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let timeout = self
+            .timeout_secs
+            .map(Duration::from_secs)
+            .unwrap_or(WORKFLOW_TIMEOUT);
+        let route = format!(
+            "repos/{}/{}/actions/workflows/{}/runs",
+            repository.owner, repository.name, self.workflow
+        );
+        let parameters = [("branch", self.git_ref.as_str()), ("per_page", "1")];
+        // Github dates the runs to the second.
+        let started = chrono::Utc::now().trunc_subsecs(0);
+        let wait = async {
+            // Id of the old run seen completed first, `null` if a run was seen in progress first:
+            // the completed runs with another id are the ones waited for.
+            let mut stale = None;
+            loop {
+                let runs: serde_json::Value =
+                    rest::get(octocrab, &route, Some(&parameters)).await?;
+                let run = &runs["workflow_runs"][0];
+                let is_recent = run["created_at"]
+                    .as_str()
+                    .and_then(|created_at| chrono::DateTime::parse_from_rfc3339(created_at).ok())
+                    .is_some_and(|created_at| created_at >= started);
+                let is_new = is_recent || stale.as_ref().is_some_and(|stale| run["id"] != *stale);
+                if run["status"] == "completed" {
+                    if is_new {
+                        return Ok(Response::WaitForWorkflow(run.clone()));
+                    }
+                    stale.get_or_insert_with(|| run["id"].clone());
+                } else if !run.is_null() {
+                    stale.get_or_insert(serde_json::Value::Null);
+                }
+                tokio::time::sleep(ctx.settings.poll_interval()).await;
+            }
+        };
+        tokio::time::timeout(timeout, wait)
+            .await
+            .unwrap_or(Err(Error::Timeout(timeout)))
+    }
+}
+
+/// Placeholder of the autolink URL templates, replaced by the reference.
+const AUTOLINK_PLACEHOLDER: &str = "<num>";
+
+/// Link the references starting with `key-prefix`, like `JIRA-123`, to an external tracker.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct CreateAutolinkOptions {
+    pub key_prefix: String,
+    /// URL of a reference, where `<num>` is replaced by what follows the prefix.
+    pub url_template: String,
+    /// Whether the references can contain letters, not only digits. Defaults to true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_alphanumeric: Option<bool>,
+}
+
+impl CreateAutolinkOptions {
+    pub fn validate(&self) -> Result<(), Error> {
+        match self.url_template.contains(AUTOLINK_PLACEHOLDER) {
+            true => Ok(()),
+            false => Err(Error::Validation(format!(
+                "the url-template of the autolink {} has no {} placeholder",
+                self.key_prefix, AUTOLINK_PLACEHOLDER
+            ))),
+        }
+    }
+
+    pub async fn run(
+        &self,
+        octocrab: &Octocrab,
+        repository: &Repository,
+    ) -> Result<Response, Error> {
+        let route = format!("repos/{}/{}/autolinks", repository.owner, repository.name);
+        let mut body = serde_json::json!({
+            "key_prefix": self.key_prefix,
+            "url_template": self.url_template,
+        });
+        if let Some(is_alphanumeric) = self.is_alphanumeric {
+            body["is_alphanumeric"] = is_alphanumeric.into();
+        }
+        let autolink = rest::post(octocrab, &route, &body).await?;
+        Ok(Response::CreateAutolink(autolink))
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+pub enum Response {
+    CreateLabel(Label),
+    /// Name of the deleted label.
+    DeleteLabel(String),
+    SyncLabels(LabelSync),
+    CreateIssue(Issue),
+    /// The issue after the update.
+    UpdateIssue(Issue),
+    CreateMilestone(Milestone),
+    CreateTeam(Team),
+    /// The repository added to the team.
+    AddTeamRepository(Repository),
+    SyncCodeownersTeam(CodeownersTeamSync),
+    SyncTeamRepos(TeamReposSync),
+    /// The organization after the update.
+    UpdateOrg(octocrab::models::orgs::Organization),
+    CreateGist(Gist),
+    /// The gist after the update.
+    UpdateGist(Gist),
+    CreateBranch(Ref),
+    EnableAutoMerge(serde_json::Value),
+    /// The repository linked to the project.
+    LinkRepositoryToProject(serde_json::Value),
+    /// The protection of the branch after the update.
+    ProtectBranch(serde_json::Value),
+    /// The repository after the update.
+    UpdateRepositorySettings(octocrab::models::Repository),
+    /// The repository whose Actions permissions were set.
+    SetActionsPermissions(Repository),
+    /// The subscription of the authenticated user after the update.
+    SetSubscription(serde_json::Value),
+    /// The ruleset created or updated.
+    CreateRuleset(serde_json::Value),
+    /// The completed run of the workflow, whose `conclusion` tells how it went.
+    WaitForWorkflow(serde_json::Value),
+    CreateAutolink(serde_json::Value),
+    /// What the command would do in dry-run mode, found out from what it read.
+    /// It completes as `Outcome::DryRun`.
+    Preview(String),
+}
+
+impl Response {
+    /// The page of what the command created or updated on Github, if it has one.
+    pub fn url(&self) -> Option<String> {
+        let html_url = |value: &serde_json::Value| value["html_url"].as_str().map(str::to_string);
+        match self {
+            Self::CreateLabel(label) => Some(label.url.to_string()),
+            Self::CreateIssue(issue) | Self::UpdateIssue(issue) => Some(issue.html_url.to_string()),
+            Self::CreateMilestone(milestone) => Some(milestone.html_url.to_string()),
+            Self::CreateTeam(team) => Some(team.html_url.to_string()),
+            Self::AddTeamRepository(repository) | Self::SetActionsPermissions(repository) => {
+                Some(format!(
+                    "https://github.com/{}/{}",
+                    repository.owner, repository.name
+                ))
+            }
+            Self::UpdateOrg(org) => org.html_url.as_ref().map(ToString::to_string),
+            Self::CreateGist(gist) | Self::UpdateGist(gist) => Some(gist.html_url.to_string()),
+            Self::CreateBranch(reference) => Some(reference.url.to_string()),
+            Self::UpdateRepositorySettings(repository) => {
+                repository.html_url.as_ref().map(ToString::to_string)
+            }
+            Self::EnableAutoMerge(value)
+            | Self::ProtectBranch(value)
+            | Self::CreateRuleset(value)
+            | Self::WaitForWorkflow(value) => html_url(value),
+            Self::DeleteLabel(_)
+            | Self::SyncLabels(_)
+            | Self::LinkRepositoryToProject(_)
+            | Self::SetSubscription(_)
+            | Self::SyncCodeownersTeam(_)
+            | Self::SyncTeamRepos(_)
+            | Self::CreateAutolink(_)
+            | Self::Preview(_) => None,
+        }
+    }
+}
+
+/// What happened when running a command on a repository.
+#[allow(clippy::large_enum_variant)]
+pub enum Outcome {
+    Succeeded(Response),
+    Failed(Error),
+    /// The command wasn't run, for the given reason.
+    Skipped(String),
+    /// The command wasn't run because of the dry-run mode, with a description of what it would do.
+    DryRun(String),
+}
+
+impl From<Result<Response, Error>> for Outcome {
+    fn from(result: Result<Response, Error>) -> Self {
+        match result {
+            Ok(response) => Outcome::Succeeded(response),
+            Err(err) => Outcome::Failed(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::RepositoryCache;
+    use crate::octomate::{Batch, ClientOptions, Context, Octomate, Settings};
+    use crate::rollback::RollbackLog;
+    use crate::summary::Summary;
+    use mockito::{mock, Matcher};
+
+    async fn octomate() -> Octomate {
+        Octomate::with_options(
+            "test",
+            &ClientOptions {
+                base_url: Some(mockito::server_url()),
+                ..ClientOptions::default()
+            },
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_create_branch_from_default_branch() {
+        let repository = mock("GET", "/repos/me/repo1")
+            .with_status(200)
+            .with_body(
+                r#"{
+                    "id": 1,
+                    "name": "repo1",
+                    "url": "https://api.github.com/repos/me/repo1",
+                    "default_branch": "trunk"
+                }"#,
+            )
+            .expect(1)
+            .create();
+        let _trunk = mock("GET", "/repos/me/repo1/git/ref/heads/trunk")
+            .with_status(200)
+            .with_body(
+                r#"{
+                    "ref": "refs/heads/trunk",
+                    "node_id": "MDM6UmVmcmVmcy9oZWFkcy90cnVuaw==",
+                    "url": "https://api.github.com/repos/me/repo1/git/refs/heads/trunk",
+                    "object": {
+                        "type": "commit",
+                        "sha": "aa218f56b14c9653891f9e74264a383fa43fefbd",
+                        "url": "https://api.github.com/repos/me/repo1/git/commits/aa218f56b14c9653891f9e74264a383fa43fefbd"
+                    }
+                }"#,
+            )
+            .expect(2)
+            .create();
+        let create = |name: &str| {
+            mock("POST", "/repos/me/repo1/git/refs")
+                .match_body(Matcher::Json(serde_json::json!({
+                    "ref": format!("refs/heads/{}", name),
+                    "sha": "aa218f56b14c9653891f9e74264a383fa43fefbd",
+                })))
+                .with_status(201)
+                .with_body(format!(
+                    r#"{{
+                        "ref": "refs/heads/{name}",
+                        "node_id": "MDM6UmVmcmVmcy9oZWFkcy9mZWF0dXJl",
+                        "url": "https://api.github.com/repos/me/repo1/git/refs/heads/{name}",
+                        "object": {{
+                            "type": "commit",
+                            "sha": "aa218f56b14c9653891f9e74264a383fa43fefbd",
+                            "url": "https://api.github.com/repos/me/repo1/git/commits/aa218f56b14c9653891f9e74264a383fa43fefbd"
+                        }}
+                    }}"#,
+                    name = name
+                ))
+                .expect(1)
+                .create()
+        };
+        let feature = create("feature");
+        let release = create("release");
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - create-branch:
+              name: feature
+          - create-branch:
+              name: release
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+
+        let refs: Vec<String> = results[0][0]
+            .iter()
+            .flatten()
+            .map(|result| match result {
+                Outcome::Succeeded(Response::CreateBranch(branch)) => branch.ref_field.clone(),
+                _ => panic!("expected a created branch"),
+            })
+            .collect();
+        assert_eq!(refs, vec!["refs/heads/feature", "refs/heads/release"]);
+        repository.assert();
+        feature.assert();
+        release.assert();
+    }
+
+    #[tokio::test]
+    async fn test_create_branch_from_tag_and_sha() {
+        let _tag = mock("GET", "/repos/me/tagged/git/ref/tags/v1.0")
+            .with_status(200)
+            .with_body(
+                r#"{
+                    "ref": "refs/tags/v1.0",
+                    "node_id": "MDM6UmVmcmVmcy90YWdzL3YxLjA=",
+                    "url": "https://api.github.com/repos/me/tagged/git/refs/tags/v1.0",
+                    "object": {
+                        "type": "tag",
+                        "sha": "5e4e4ae9bf3ab263ed6f9ed0d2a3d6ee3a6e7f4b",
+                        "url": "https://api.github.com/repos/me/tagged/git/tags/5e4e4ae9bf3ab263ed6f9ed0d2a3d6ee3a6e7f4b"
+                    }
+                }"#,
+            )
+            .expect(1)
+            .create();
+        let _annotated = mock(
+            "GET",
+            "/repos/me/tagged/git/tags/5e4e4ae9bf3ab263ed6f9ed0d2a3d6ee3a6e7f4b",
+        )
+        .with_status(200)
+        .with_body(
+            r#"{
+                "tag": "v1.0",
+                "sha": "5e4e4ae9bf3ab263ed6f9ed0d2a3d6ee3a6e7f4b",
+                "object": {
+                    "type": "commit",
+                    "sha": "c3d0be41ecbe669545ee3e94d31ed9a4bc91ee3c"
+                }
+            }"#,
+        )
+        .expect(1)
+        .create();
+        let create = |name: &str, sha: &str| {
+            mock("POST", "/repos/me/tagged/git/refs")
+                .match_body(Matcher::Json(serde_json::json!({
+                    "ref": format!("refs/heads/{}", name),
+                    "sha": sha,
+                })))
+                .with_status(201)
+                .with_body(format!(
+                    r#"{{
+                        "ref": "refs/heads/{name}",
+                        "node_id": "MDM6UmVmcmVmcy9oZWFkcy9mZWF0dXJl",
+                        "url": "https://api.github.com/repos/me/tagged/git/refs/heads/{name}",
+                        "object": {{
+                            "type": "commit",
+                            "sha": "{sha}",
+                            "url": "https://api.github.com/repos/me/tagged/git/commits/{sha}"
+                        }}
+                    }}"#,
+                    name = name,
+                    sha = sha
+                ))
+                .expect(1)
+                .create()
+        };
+        let hotfix = create("hotfix", "c3d0be41ecbe669545ee3e94d31ed9a4bc91ee3c");
+        let bisect = create("bisect", "aa218f56b14c9653891f9e74264a383fa43fefbd");
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: tagged
+    steps:
+      - runs:
+          - create-branch:
+              name: hotfix
+              from-ref: tags/v1.0
+          - create-branch:
+              name: bisect
+              from-ref: aa218f56b14c9653891f9e74264a383fa43fefbd
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+
+        assert!(results[0][0]
+            .iter()
+            .flatten()
+            .all(|result| matches!(result, Outcome::Succeeded(Response::CreateBranch(_)))));
+        hotfix.assert();
+        bisect.assert();
+    }
+
+    #[tokio::test]
+    async fn test_create_issue_with_invalid_assignee() {
+        let valid = mock("GET", "/repos/me/repo1/assignees/thor")
+            .with_status(204)
+            .expect(1)
+            .create();
+        let invalid = mock("GET", "/repos/me/repo1/assignees/loki")
+            .with_status(404)
+            .with_body(r#"{"message": "Not Found"}"#)
+            .expect(1)
+            .create();
+        let issue = mock("POST", "/repos/me/repo1/issues")
+            .with_status(201)
+            .expect(0)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - create-issue:
+              title: "Hello"
+              body: "World"
+              assignees:
+                - thor
+                - loki
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let octomate = octomate().await.with_settings(Settings {
+            validate_assignees: true,
+            ..Settings::default()
+        });
+        let results = octomate.run_batch(&batch).await;
+
+        match &results[0][0][0][0] {
+            Outcome::Failed(Error::InvalidAssignees { assignees, .. }) => {
+                assert_eq!(assignees, &vec!["loki".to_string()])
+            }
+            _ => panic!("expected invalid assignees"),
+        }
+        valid.assert();
+        invalid.assert();
+        issue.assert();
+    }
+
+    #[tokio::test]
+    async fn test_enable_auto_merge() {
+        let node_id = mock("POST", "/graphql")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex("pullRequest\\(number".to_string()),
+                Matcher::PartialJson(serde_json::json!({
+                    "variables": { "owner": "me", "name": "repo1", "number": 42 }
+                })),
+            ]))
+            .with_status(200)
+            .with_body(r#"{"data": {"repository": {"pullRequest": {"id": "PR_kwDOA"}}}}"#)
+            .expect(1)
+            .create();
+        let mutation = mock("POST", "/graphql")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex("enablePullRequestAutoMerge".to_string()),
+                Matcher::PartialJson(serde_json::json!({
+                    "variables": { "pullRequestId": "PR_kwDOA", "mergeMethod": "SQUASH" }
+                })),
+            ]))
+            .with_status(200)
+            .with_body(
+                r#"{"data": {"enablePullRequestAutoMerge": {"pullRequest": {
+                    "number": 42,
+                    "autoMergeRequest": {"enabledAt": "2022-05-01T00:00:00Z", "mergeMethod": "SQUASH"}
+                }}}}"#,
+            )
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - enable-auto-merge:
+              number: 42
+              method: squash
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+
+        match &results[0][0][0][0] {
+            Outcome::Succeeded(Response::EnableAutoMerge(pull_request)) => {
+                assert_eq!(pull_request["autoMergeRequest"]["mergeMethod"], "SQUASH")
+            }
+            _ => panic!("expected auto-merge to be enabled"),
+        }
+        node_id.assert();
+        mutation.assert();
+
+        let missing = mock("POST", "/graphql")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "variables": { "owner": "me", "name": "merged", "number": 42 }
+            })))
+            .with_status(200)
+            .with_body(r#"{"data": {"repository": {"pullRequest": null}}}"#)
+            .expect(1)
+            .create();
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: merged
+    steps:
+      - runs:
+          - enable-auto-merge:
+              number: 42
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+        assert!(matches!(
+            &results[0][0][0][0],
+            Outcome::Failed(Error::MissingPullRequest { number: 42, .. })
+        ));
+        missing.assert();
+        mutation.assert();
+    }
+
+    #[tokio::test]
+    async fn test_link_repository_to_project() {
+        let metadata = mock("GET", "/repos/me/tracked")
+            .with_status(200)
+            .with_body(
+                r#"{
+                    "id": 1,
+                    "node_id": "R_kgDOA",
+                    "name": "tracked",
+                    "url": "https://api.github.com/repos/me/tracked"
+                }"#,
+            )
+            .expect(1)
+            .create();
+        let mutation = mock("POST", "/graphql")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::Regex("linkProjectV2ToRepository".to_string()),
+                Matcher::PartialJson(serde_json::json!({
+                    "variables": { "projectId": "PVT_kwDOA", "repositoryId": "R_kgDOA" }
+                })),
+            ]))
+            .with_status(200)
+            .with_body(
+                r#"{"data": {"linkProjectV2ToRepository": {
+                    "repository": {"id": "R_kgDOA", "nameWithOwner": "me/tracked"}
+                }}}"#,
+            )
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: tracked
+    steps:
+      - runs:
+          - link-repository-to-project:
+              project-id: PVT_kwDOA
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+
+        match &results[0][0][0][0] {
+            Outcome::Succeeded(Response::LinkRepositoryToProject(repository)) => {
+                assert_eq!(repository["nameWithOwner"], "me/tracked")
+            }
+            _ => panic!("expected the repository to be linked"),
+        }
+        metadata.assert();
+        mutation.assert();
+    }
+
+    #[test]
+    fn test_enable_auto_merge_invalid_method() {
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories: []
+    steps:
+      - runs:
+          - enable-auto-merge:
+              number: 42
+              method: octopus
+"#
+            .as_bytes(),
+        );
+        assert!(matches!(batch, Err(Error::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_for_each_repository_keeps_order() {
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: slow
+      - owner: me
+        name: medium
+      - owner: me
+        name: fast
+    steps: []
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let settings = Settings::default();
+        let cache = RepositoryCache::default();
+        let ctx = Context::new(&batch, &settings, &cache, Some(&batch.jobs[0]), None);
+
+        let results = for_each_repository(&ctx, |repository| async move {
+            let delay = match repository.name.as_str() {
+                "slow" => 60,
+                "medium" => 30,
+                _ => 0,
+            };
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            Err(Error::Validation(repository.name.clone()))
+        })
+        .await;
+
+        let names: Vec<String> = results
+            .into_iter()
+            .map(|result| match result {
+                Outcome::Failed(Error::Validation(name)) => name,
+                _ => panic!("unexpected result"),
+            })
+            .collect();
+        assert_eq!(names, vec!["slow", "medium", "fast"]);
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Body, Server};
+        use std::convert::Infallible;
+
+        // Each request takes a while, the three of a command longer than the timeout.
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|_request| async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok::<_, Infallible>(hyper::Response::new(Body::from("{}")))
+            }))
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        let octocrab = Octocrab::builder().base_url(url).unwrap().build().unwrap();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+    steps: []
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let cache = RepositoryCache::default();
+        let run = |timeout: u64, requests: usize| {
+            let settings = Settings {
+                request_timeout: Some(Duration::from_millis(timeout)),
+                ..Settings::default()
+            };
+            let (batch, cache, octocrab) = (&batch, &cache, &octocrab);
+            async move {
+                let ctx = Context::new(batch, &settings, cache, Some(&batch.jobs[0]), None);
+                for_each_repository(&ctx, |_| async {
+                    for _ in 0..requests {
+                        rest::get::<serde_json::Value>(octocrab, "slow", None::<&()>).await?;
+                    }
+                    Ok(Response::SetSubscription(serde_json::json!({})))
+                })
+                .await
+                .remove(0)
+            }
+        };
+
+        assert!(matches!(run(120, 3).await, Outcome::Succeeded(_)));
+        assert!(matches!(
+            run(10, 1).await,
+            Outcome::Failed(Error::Timeout(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let label = mock("POST", "/repos/me/repo1/labels").expect(0).create();
+        let gist = mock("POST", "/gists").expect(0).create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - create-label:
+              name: "bug"
+              color: "f29513"
+              description: "Something isn't working"
+          - create-gist:
+              title: "config"
+              content: "hello"
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let octomate = octomate().await.with_settings(Settings {
+            dry_run: true,
+            ..Settings::default()
+        });
+        let results = octomate.run_batch(&batch).await;
+
+        let descriptions: Vec<String> = results[0][0]
+            .iter()
+            .flatten()
+            .map(|outcome| match outcome {
+                Outcome::DryRun(description) => description.clone(),
+                _ => panic!("expected a dry-run outcome"),
+            })
+            .collect();
+        assert_eq!(
+            descriptions,
+            vec!["create-label on me/repo1", "create-gist"]
+        );
+        label.assert();
+        gist.assert();
+    }
+
+    #[tokio::test]
+    async fn test_outside_of_job_is_skipped() {
+        let batch = Batch::try_from("version: \"1.0\"\njobs: []".as_bytes()).unwrap();
+        let settings = Settings::default();
+        let cache = RepositoryCache::default();
+        let ctx = Context::new(&batch, &settings, &cache, None, None);
+
+        let results = for_each_repository(&ctx, |repository| async move {
+            Err(Error::Validation(repository.name.clone()))
+        })
+        .await;
+        assert!(matches!(&results[..], [Outcome::Skipped(_)]));
+    }
+
+    const ISSUE: &str = r#"{
+        "id": 1,
+        "node_id": "MDU6SXNzdWUx",
+        "url": "https://api.github.com/repos/me/repo1/issues/1",
+        "repository_url": "https://api.github.com/repos/me/repo1",
+        "labels_url": "https://api.github.com/repos/me/repo1/issues/1/labels{/name}",
+        "comments_url": "https://api.github.com/repos/me/repo1/issues/1/comments",
+        "events_url": "https://api.github.com/repos/me/repo1/issues/1/events",
+        "html_url": "https://github.com/me/repo1/issues/1",
+        "number": 1,
+        "state": "open",
+        "title": "Login is broken",
+        "body": "The Login is broken on 1.2.",
+        "user": {
+            "login": "me",
+            "id": 1,
+            "node_id": "MDQ6VXNlcjE=",
+            "avatar_url": "https://github.com/images/error/me.gif",
+            "gravatar_id": "",
+            "url": "https://api.github.com/users/me",
+            "html_url": "https://github.com/me",
+            "followers_url": "https://api.github.com/users/me/followers",
+            "following_url": "https://api.github.com/users/me/following{/other_user}",
+            "gists_url": "https://api.github.com/users/me/gists{/gist_id}",
+            "starred_url": "https://api.github.com/users/me/starred{/owner}{/repo}",
+            "subscriptions_url": "https://api.github.com/users/me/subscriptions",
+            "organizations_url": "https://api.github.com/users/me/orgs",
+            "repos_url": "https://api.github.com/users/me/repos",
+            "events_url": "https://api.github.com/users/me/events{/privacy}",
+            "received_events_url": "https://api.github.com/users/me/received_events",
+            "type": "User",
+            "site_admin": false
+        },
+        "labels": [],
+        "assignees": [],
+        "author_association": "OWNER",
+        "locked": false,
+        "comments": 0,
+        "created_at": "2022-05-01T00:00:00Z",
+        "updated_at": "2022-05-01T00:00:00Z"
+    }"#;
+
+    #[tokio::test]
+    async fn test_create_issue_from_template() {
+        let template = mock(
+            "GET",
+            "/repos/me/repo1/contents/.github/ISSUE_TEMPLATE/bug.md",
+        )
+        .with_status(200)
+        .with_body(
+            r#"{
+                "name": "bug.md",
+                "path": ".github/ISSUE_TEMPLATE/bug.md",
+                "sha": "3d21ec53a331a6f037a91c368710b99387d012c1",
+                "size": 75,
+                "url": "https://api.github.com/repos/me/repo1/contents/.github/ISSUE_TEMPLATE/bug.md",
+                "type": "file",
+                "encoding": "base64",
+                "content": "LS0tCm5hbWU6IEJ1ZyByZXBvcnQKYWJvdXQ6IFJlcG9ydCBhIGJ1ZwotLS0KVGhlIHt7IGNvbXBvbmVudCB9fSBpcyBicm9rZW4gb24ge3t2ZXJzaW9ufX0uCg==",
+                "_links": {
+                    "self": "https://api.github.com/repos/me/repo1/contents/.github/ISSUE_TEMPLATE/bug.md",
+                    "git": "https://api.github.com/repos/me/repo1/git/blobs/3d21ec53a331a6f037a91c368710b99387d012c1",
+                    "html": "https://github.com/me/repo1/blob/main/.github/ISSUE_TEMPLATE/bug.md"
+                }
+            }"#,
+        )
+        .expect(1)
+        .create();
+        let missing = mock(
+            "GET",
+            "/repos/me/repo2/contents/.github/ISSUE_TEMPLATE/bug.md",
+        )
+        .with_status(404)
+        .with_body(r#"{"message": "Not Found"}"#)
+        .expect(1)
+        .create();
+        let issue = mock("POST", "/repos/me/repo1/issues")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "title": "Login is broken",
+                "body": "The Login is broken on 1.2.\n",
+            })))
+            .with_status(201)
+            .with_body(ISSUE)
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+      - owner: me
+        name: repo2
+    steps:
+      - runs:
+          - create-issue-from-template:
+              template: bug.md
+              title: "{{ component }} is broken"
+              fields:
+                component: Login
+                version: "1.2"
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+
+        match &results[0][0][0][..] {
+            [Outcome::Succeeded(Response::CreateIssue(issue)), Outcome::Failed(Error::MissingTemplate { template, .. })] =>
+            {
+                assert_eq!(issue.number, 1);
+                assert_eq!(template, "bug.md");
+            }
+            _ => panic!("expected an issue for repo1 and a missing template for repo2"),
+        }
+        template.assert();
+        missing.assert();
+        issue.assert();
+    }
+
+    fn label(repository: &str, name: &str, color: &str, description: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": 208045946,
+            "node_id": "MDU6TGFiZWwyMDgwNDU5NDY=",
+            "url": format!("https://api.github.com/repos/me/{}/labels/{}", repository, name),
+            "name": name,
+            "description": description,
+            "color": color,
+            "default": false
+        })
+    }
+
+    #[tokio::test]
+    async fn test_copy_labels() {
+        let source = mock("GET", "/repos/me/canonical/labels")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_body(
+                serde_json::json!([
+                    label("canonical", "bug", "f29513", "Something isn't working"),
+                    label("canonical", "docs", "0075ca", "Documentation"),
+                ])
+                .to_string(),
+            )
+            .expect(1)
+            .create();
+        let target = mock("GET", "/repos/me/repo1/labels")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_body(
+                serde_json::json!([
+                    label("repo1", "bug", "000000", "Outdated"),
+                    label("repo1", "area/legacy", "ffffff", ""),
+                ])
+                .to_string(),
+            )
+            .expect(1)
+            .create();
+        let update = mock("PATCH", "/repos/me/repo1/labels/bug")
+            .match_body(Matcher::Json(serde_json::json!({
+                "new_name": "bug",
+                "color": "f29513",
+                "description": "Something isn't working",
+            })))
+            .with_status(200)
+            .with_body(label("repo1", "bug", "f29513", "Something isn't working").to_string())
+            .expect(1)
+            .create();
+        let create = mock("POST", "/repos/me/repo1/labels")
+            .match_body(Matcher::PartialJson(serde_json::json!({"name": "docs"})))
+            .with_status(201)
+            .with_body(label("repo1", "docs", "0075ca", "Documentation").to_string())
+            .expect(1)
+            .create();
+        let prune = mock("DELETE", "/repos/me/repo1/labels/area%2Flegacy")
+            .with_status(204)
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - copy-labels:
+              from-owner: me
+              from-repo: canonical
+              prune: true
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+
+        match &results[0][0][0][0] {
+            Outcome::Succeeded(Response::SyncLabels(sync)) => {
+                let changes = LabelChanges {
+                    created: vec!["docs".to_string()],
+                    updated: vec!["bug".to_string()],
+                    pruned: vec!["area/legacy".to_string()],
+                };
+                assert_eq!(sync.planned, changes);
+                assert_eq!(sync.applied, changes);
+            }
+            _ => panic!("expected the labels to be copied"),
+        }
+        source.assert();
+        target.assert();
+        update.assert();
+        create.assert();
+        prune.assert();
+    }
+
+    #[tokio::test]
+    async fn test_add_team_repository() {
+        let m = mock("PUT", "/orgs/avengers/teams/heroes/repos/me/repo1")
+            .match_body(Matcher::Json(serde_json::json!({"permission": "maintain"})))
+            .with_status(204)
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - add-team-repository:
+              org: avengers
+              team-slug: heroes
+              permission: maintain
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+
+        match &results[0][0][0][0] {
+            Outcome::Succeeded(Response::AddTeamRepository(repository)) => {
+                assert_eq!(repository.name, "repo1")
+            }
+            _ => panic!("expected the repository to be added to the team"),
+        }
+        m.assert();
+
+        let invalid = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories: []
+    steps:
+      - runs:
+          - add-team-repository:
+              org: avengers
+              team-slug: heroes
+              permission: write
+"#
+            .as_bytes(),
+        );
+        assert!(matches!(invalid, Err(Error::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_set_org_default_permission() {
+        let url = "https://api.github.com/orgs/guardians";
+        let m = mock("PATCH", "/orgs/guardians")
+            .match_body(Matcher::Json(
+                serde_json::json!({"default_repository_permission": "read"}),
+            ))
+            .with_status(200)
+            .with_body(
+                serde_json::json!({
+                    "login": "guardians",
+                    "id": 1,
+                    "node_id": "MDEyOk9yZ2FuaXphdGlvbjE=",
+                    "url": url,
+                    "repos_url": format!("{}/repos", url),
+                    "events_url": format!("{}/events", url),
+                    "hooks_url": format!("{}/hooks", url),
+                    "issues_url": format!("{}/issues", url),
+                    "members_url": format!("{}/members", url),
+                    "public_members_url": format!("{}/public_members", url),
+                    "avatar_url": "https://github.com/images/error/octocat_happy.gif"
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create();
+
+        let batch = |permission: &str| {
+            Batch::try_from(
+                format!(
+                    r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: guardians
+        name: repo1
+      - owner: guardians
+        name: repo2
+    steps:
+      - runs:
+          - set-org-default-permission:
+              org: guardians
+              default-repository-permission: {}
+"#,
+                    permission
+                )
+                .as_bytes(),
+            )
+        };
+        let results = octomate().await.run_batch(&batch("read").unwrap()).await;
+
+        assert_eq!(results[0][0][0].len(), 1);
+        assert!(matches!(
+            results[0][0][0][0],
+            Outcome::Succeeded(Response::UpdateOrg(_))
+        ));
+        m.assert();
+        assert!(matches!(batch("maintain"), Err(Error::Validation(_))));
+    }
+
+    fn milestone(repo: &str, number: u64, title: &str) -> serde_json::Value {
+        serde_json::json!({
+            "url": format!("https://api.github.com/repos/me/{}/milestones/{}", repo, number),
+            "html_url": format!("https://github.com/me/{}/milestone/{}", repo, number),
+            "id": number,
+            "node_id": "MDk6TWlsZXN0b25lMQ==",
+            "number": number,
+            "title": title,
+            "created_at": "2011-04-10T20:09:31Z"
+        })
+    }
+
+    #[tokio::test]
+    async fn test_create_milestone_then_issues_in_it() {
+        // repo1 already has the milestone as number 3, repo2 gets it as number 1.
+        let list_repo1 = mock("GET", "/repos/me/repo1/milestones")
+            .match_query(Matcher::UrlEncoded("state".into(), "all".into()))
+            .with_status(200)
+            .with_body(serde_json::json!([milestone("repo1", 3, "v1")]).to_string())
+            .expect(2)
+            .create();
+        let list_repo2_before = mock("GET", "/repos/me/repo2/milestones")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_body("[]")
+            .expect(1)
+            .create();
+        let list_repo2_after = mock("GET", "/repos/me/repo2/milestones")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_body(serde_json::json!([milestone("repo2", 1, "v1")]).to_string())
+            .expect(1)
+            .create();
+        let create_milestone = mock("POST", "/repos/me/repo2/milestones")
+            .match_body(Matcher::PartialJson(serde_json::json!({"title": "v1"})))
+            .with_status(201)
+            .with_body(milestone("repo2", 1, "v1").to_string())
+            .expect(1)
+            .create();
+        let issue_repo1 = mock("POST", "/repos/me/repo1/issues")
+            .match_body(Matcher::PartialJson(serde_json::json!({"milestone": 3})))
+            .with_status(201)
+            .with_body(ISSUE)
+            .expect(1)
+            .create();
+        let issue_repo2 = mock("POST", "/repos/me/repo2/issues")
+            .match_body(Matcher::PartialJson(serde_json::json!({"milestone": 1})))
+            .with_status(201)
+            .with_body(ISSUE)
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+      - owner: me
+        name: repo2
+    steps:
+      - runs:
+          - create-milestone:
+              title: v1
+              if-exists: skip
+      - runs:
+          - create-issue:
+              title: Release v1
+              body: Checklist
+              milestone-title: v1
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+
+        for outcomes in results[0].iter().flatten() {
+            assert!(outcomes
+                .iter()
+                .all(|outcome| matches!(outcome, Outcome::Succeeded(_))));
+        }
+        list_repo1.assert();
+        list_repo2_before.assert();
+        list_repo2_after.assert();
+        create_milestone.assert();
+        issue_repo1.assert();
+        issue_repo2.assert();
+    }
+
+    #[tokio::test]
+    async fn test_set_issue_milestone_by_title() {
+        let list = mock("GET", "/repos/me/bucketed/milestones")
+            .match_query(Matcher::UrlEncoded("state".into(), "all".into()))
+            .with_status(200)
+            .with_body(
+                serde_json::json!([
+                    milestone("bucketed", 2, "v1"),
+                    milestone("bucketed", 5, "v2")
+                ])
+                .to_string(),
+            )
+            .expect(1)
+            .create();
+        let update = mock("PATCH", "/repos/me/bucketed/issues/42")
+            .match_body(Matcher::Json(serde_json::json!({"milestone": 5})))
+            .with_status(200)
+            .with_body(ISSUE)
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: bucketed
+    steps:
+      - runs:
+          - set-issue-milestone:
+              number: 42
+              milestone-title: v2
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+
+        assert!(matches!(
+            results[0][0][0][0],
+            Outcome::Succeeded(Response::UpdateIssue(_))
+        ));
+        list.assert();
+        update.assert();
+
+        let both = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories: []
+    steps:
+      - runs:
+          - set-issue-milestone:
+              number: 42
+              milestone: 5
+              milestone-title: v2
+"#
+            .as_bytes(),
+        );
+        assert!(matches!(both, Err(Error::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_update_gist() {
+        let url = "https://api.github.com/gists/aa5a315d";
+        let m = mock("PATCH", "/gists/aa5a315d")
+            .match_body(Matcher::Json(serde_json::json!({
+                "description": "Our snippets",
+                "files": {
+                    "snippet.rs": { "content": "fn main() {}" },
+                    "obsolete.rs": null
+                }
+            })))
+            .with_status(200)
+            .with_body(
+                serde_json::json!({
+                    "comments": 0,
+                    "comments_url": format!("{}/comments", url),
+                    "commits_url": format!("{}/commits", url),
+                    "created_at": "2022-05-01T00:00:00Z",
+                    "description": "Our snippets",
+                    "files": {},
+                    "forks_url": format!("{}/forks", url),
+                    "git_pull_url": "https://gist.github.com/aa5a315d.git",
+                    "git_push_url": "https://gist.github.com/aa5a315d.git",
+                    "html_url": "https://gist.github.com/aa5a315d",
+                    "id": "aa5a315d",
+                    "node_id": "MDQ6R2lzdGFhNWEzMTVk",
+                    "updated_at": "2022-05-02T00:00:00Z",
+                    "url": url
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+      - owner: me
+        name: repo2
+    steps:
+      - runs:
+          - update-gist:
+              gist-id: aa5a315d
+              description: Our snippets
+              files:
+                - name: snippet.rs
+                  content: "fn main() {}"
+                - name: obsolete.rs
+                  content: ""
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+
+        assert_eq!(results[0][0][0].len(), 1);
+        match &results[0][0][0][0] {
+            Outcome::Succeeded(response @ Response::UpdateGist(_)) => assert_eq!(
+                response.url().as_deref(),
+                Some("https://gist.github.com/aa5a315d")
+            ),
+            _ => panic!("expected the gist to be updated"),
+        }
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn test_create_label_list() {
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+      - owner: me
+        name: repo2
+    steps:
+      - runs:
+          - create-label:
+              - name: bug
+                color: "d73a4a"
+                description: "Something isn't working"
+              - name: docs
+                color: auto
+                description: "Improvements to the documentation"
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        match &batch.jobs[0].steps[0].runs[0] {
+            Command::CreateLabel(CreateLabels::Many(labels)) => {
+                assert_eq!(labels[1].name, "docs")
+            }
+            command => panic!("expected a list of labels, got {:?}", command),
+        }
+        assert_eq!(batch.unit_count(), 4);
+
+        let results = octomate()
+            .await
+            .with_settings(Settings {
+                dry_run: true,
+                ..Settings::default()
+            })
+            .run_batch(&batch)
+            .await;
+        let outcomes: Vec<String> = results[0][0][0]
+            .iter()
+            .map(|outcome| match outcome {
+                Outcome::DryRun(description) => description.clone(),
+                _ => panic!("expected a dry run"),
+            })
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                "create-label on me/repo1",
+                "create-label on me/repo1",
+                "create-label on me/repo2",
+                "create-label on me/repo2",
+            ]
+        );
+        let command = &batch.jobs[0].steps[0].runs[0];
+        assert_eq!(
+            command
+                .repository_of(&batch.jobs[0], 2)
+                .map(|repository| repository.name.as_str()),
+            Some("repo2")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_label_with_slash() {
+        let m = mock("DELETE", "/repos/me/repo1/labels/needs%2Ftriage%20now")
+            .with_status(204)
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - delete-label:
+              name: needs/triage now
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+        assert!(matches!(&results[0][0][0][0], Outcome::Succeeded(_)));
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn test_delete_missing_label() {
+        let m = mock("DELETE", "/repos/me/repo1/labels/gone")
+            .with_status(404)
+            .with_body(
+                r#"{"message": "Not Found", "documentation_url": "https://docs.github.com"}"#,
+            )
+            .expect(3)
+            .create();
+
+        let batch = |ignore_missing: &str| {
+            Batch::try_from(
+                format!(
+                    r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - delete-label:
+              name: gone
+              {}
+"#,
+                    ignore_missing
+                )
+                .as_bytes(),
+            )
+            .unwrap()
+        };
+        let octomate = octomate().await;
+        let results = octomate.run_batch(&batch("")).await;
+        assert!(matches!(&results[0][0][0][0], Outcome::Failed(err) if err.is_not_found()));
+
+        let octomate = octomate.with_settings(Settings {
+            ignore_missing: true,
+            ..Settings::default()
+        });
+        let results = octomate.run_batch(&batch("")).await;
+        assert!(matches!(&results[0][0][0][0], Outcome::Skipped(reason) if reason == NOT_FOUND));
+
+        let results = octomate.run_batch(&batch("ignore-missing: false")).await;
+        assert!(matches!(&results[0][0][0][0], Outcome::Failed(_)));
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn test_update_branch_protection_keeps_other_settings() {
+        let get = mock("GET", "/repos/me/repo1/branches/main/protection")
+            .with_status(200)
+            .with_body(
+                r#"{
+                    "url": "https://api.github.com/repos/me/repo1/branches/main/protection",
+                    "required_status_checks": {
+                        "strict": true,
+                        "contexts": ["ci/build"],
+                        "checks": [{ "context": "ci/build", "app_id": 15368 }]
+                    },
+                    "enforce_admins": { "enabled": true },
+                    "required_pull_request_reviews": {
+                        "dismiss_stale_reviews": false,
+                        "require_code_owner_reviews": false,
+                        "required_approving_review_count": 1,
+                        "require_last_push_approval": true,
+                        "bypass_pull_request_allowances": {
+                            "users": [{ "login": "octocat" }],
+                            "teams": [{ "slug": "release" }],
+                            "apps": []
+                        }
+                    },
+                    "required_linear_history": { "enabled": true },
+                    "lock_branch": { "enabled": true },
+                    "block_creations": { "enabled": true },
+                    "allow_fork_syncing": { "enabled": true }
+                }"#,
+            )
+            .expect(1)
+            .create();
+        let put = mock("PUT", "/repos/me/repo1/branches/main/protection")
+            .match_body(Matcher::Json(serde_json::json!({
+                "required_status_checks": {
+                    "strict": true,
+                    "contexts": ["ci/build"],
+                    "checks": [{ "context": "ci/build", "app_id": 15368 }]
+                },
+                "enforce_admins": true,
+                "required_pull_request_reviews": {
+                    "dismiss_stale_reviews": true,
+                    "require_code_owner_reviews": false,
+                    "required_approving_review_count": 2,
+                    "require_last_push_approval": true,
+                    "bypass_pull_request_allowances": {
+                        "users": ["octocat"],
+                        "teams": ["release"],
+                        "apps": []
+                    }
+                },
+                "restrictions": null,
+                "required_linear_history": true,
+                "allow_force_pushes": false,
+                "allow_deletions": false,
+                "required_conversation_resolution": false,
+                "lock_branch": true,
+                "block_creations": true,
+                "allow_fork_syncing": true
+            })))
+            .with_status(200)
+            .with_body(
+                r#"{"url": "https://api.github.com/repos/me/repo1/branches/main/protection"}"#,
+            )
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - update-branch-protection:
+              branch: main
+              required-approving-review-count: 2
+              dismiss-stale-reviews: true
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+
+        assert!(matches!(
+            &results[0][0][0][0],
+            Outcome::Succeeded(Response::ProtectBranch(_))
+        ));
+        get.assert();
+        put.assert();
+    }
+
+    #[tokio::test]
+    async fn test_copy_labels_dry_run_diff() {
+        let source = mock("GET", "/repos/me/upstream/labels")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_body(
+                serde_json::json!([
+                    label("upstream", "bug", "f29513", "Something isn't working"),
+                    label("upstream", "question", "d876e3", "Further information"),
+                ])
+                .to_string(),
+            )
+            .expect(1)
+            .create();
+        let target = mock("GET", "/repos/me/drifted/labels")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_body(
+                serde_json::json!([
+                    label("drifted", "Bug", "f29513", "Something isn't working"),
+                    label("drifted", "stale", "ffffff", ""),
+                ])
+                .to_string(),
+            )
+            .expect(1)
+            .create();
+        let changes = mock("POST", "/repos/me/drifted/labels").expect(0).create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: drifted
+    steps:
+      - runs:
+          - copy-labels:
+              from-owner: me
+              from-repo: upstream
+              prune: true
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let octomate = octomate().await.with_settings(Settings {
+            dry_run: true,
+            ..Settings::default()
+        });
+        let results = octomate.run_batch(&batch).await;
+
+        assert!(matches!(
+            &results[0][0][0][0],
+            Outcome::DryRun(description) if description
+                == r#"copy-labels on me/drifted would create ["question"], update ["bug"], prune ["stale"]"#
+        ));
+        let summary = Summary::new(&batch, &results);
+        assert_eq!(summary.total.dry_run, 1);
+        assert_eq!(summary.total.succeeded, 0);
+        let mut rollback = RollbackLog::default();
+        rollback.record(&batch, &results);
+        assert!(rollback.actions.is_empty() && rollback.not_invertible.is_empty());
+        source.assert();
+        target.assert();
+        changes.assert();
+    }
+
+    #[tokio::test]
+    async fn test_set_repository_visibility() {
+        let metadata = mock("GET", "/repos/me/exposed")
+            .with_status(200)
+            .with_body(
+                r#"{
+                    "id": 1,
+                    "name": "exposed",
+                    "url": "https://api.github.com/repos/me/exposed",
+                    "visibility": "private"
+                }"#,
+            )
+            .expect(1)
+            .create();
+        let m = mock("PATCH", "/repos/me/exposed")
+            .match_body(Matcher::Json(serde_json::json!({"visibility": "internal"})))
+            .with_status(200)
+            .with_body(
+                r#"{
+                    "id": 1,
+                    "name": "exposed",
+                    "url": "https://api.github.com/repos/me/exposed",
+                    "visibility": "internal"
+                }"#,
+            )
+            .expect(1)
+            .create();
+
+        let batch = |visibility: &str| {
+            Batch::try_from(
+                format!(
+                    r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: exposed
+    steps:
+      - runs:
+          - set-repository-visibility:
+              visibility: {}
+"#,
+                    visibility
+                )
+                .as_bytes(),
+            )
+        };
+        let internal = batch("internal").unwrap();
+        assert!(!internal.jobs[0].steps[0].runs[0].is_destructive());
+        let results = octomate().await.run_batch(&internal).await;
+        match &results[0][0][0][0] {
+            Outcome::Succeeded(Response::UpdateRepositorySettings(repository)) => {
+                assert_eq!(repository.name, "exposed")
+            }
+            _ => panic!("expected the visibility to be updated"),
+        }
+        metadata.assert();
+        m.assert();
+
+        assert!(batch("public").unwrap().jobs[0].steps[0].runs[0].is_destructive());
+        assert!(matches!(batch("secret"), Err(Error::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_set_repository_visibility_back() {
+        let repository = |visibility: &str| {
+            serde_json::json!({
+                "id": 1,
+                "name": "flipped",
+                "url": "https://api.github.com/repos/me/flipped",
+                "visibility": visibility
+            })
+            .to_string()
+        };
+        let metadata = mock("GET", "/repos/me/flipped")
+            .with_status(200)
+            .with_body(repository("private"))
+            .expect(1)
+            .create();
+        let changes: Vec<mockito::Mock> = ["internal", "private"]
+            .iter()
+            .map(|visibility| {
+                mock("PATCH", "/repos/me/flipped")
+                    .match_body(Matcher::Json(
+                        serde_json::json!({ "visibility": visibility }),
+                    ))
+                    .with_status(200)
+                    .with_body(repository(visibility))
+                    .expect(1)
+                    .create()
+            })
+            .collect();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: flipped
+    steps:
+      - runs:
+          - set-repository-visibility:
+              visibility: internal
+      - runs:
+          - set-repository-visibility:
+              visibility: private
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+        let visibilities: Vec<Option<String>> = results[0]
+            .iter()
+            .map(|step| match &step[0][0] {
+                Outcome::Succeeded(Response::UpdateRepositorySettings(repository)) => {
+                    repository.visibility.clone()
+                }
+                _ => panic!("expected the visibility to be updated"),
+            })
+            .collect();
+        assert_eq!(
+            visibilities,
+            vec![Some("internal".to_string()), Some("private".to_string())]
+        );
+        metadata.assert();
+        changes.iter().for_each(mockito::Mock::assert);
+    }
+
+    #[tokio::test]
+    async fn test_sync_codeowners_team() {
+        let content = |repository: &str, path: &str, content: &str| {
+            serde_json::json!({
+                "name": "CODEOWNERS",
+                "path": path,
+                "sha": "3d21ec53a331a6f037a91c368710b99387d012c1",
+                "size": 64,
+                "url": format!("https://api.github.com/repos/me/{}/contents/{}", repository, path),
+                "type": "file",
+                "encoding": "base64",
+                "content": content,
+                "_links": {
+                    "self": format!("https://api.github.com/repos/me/{}/contents/{}", repository, path),
+                    "html": format!("https://github.com/me/{}/blob/main/{}", repository, path)
+                }
+            })
+            .to_string()
+        };
+        // `*.rs @me/reviewers @octocat` and `/docs/ @me/writers # docs`, after a comment.
+        let routed = mock("GET", "/repos/me/routed/contents/.github/CODEOWNERS")
+            .with_status(200)
+            .with_body(content(
+                "routed",
+                ".github/CODEOWNERS",
+                "IyBSZXZpZXcgcm91dGluZwoqLnJzIEBtZS9yZXZpZXdlcnMgQG9jdG9jYXQKL2RvY3MvIEBtZS93cml0ZXJzICMgZG9jcwo=",
+            ))
+            .expect(1)
+            .create();
+        let first_teams = mock("GET", "/repos/me/routed/teams")
+            .match_query(Matcher::UrlEncoded("per_page".into(), "100".into()))
+            .with_status(200)
+            .with_header(
+                "link",
+                &format!(
+                    r#"<{}/repos/me/routed/teams?per_page=100&page=2>; rel="next""#,
+                    mockito::server_url()
+                ),
+            )
+            .with_body(r#"[{"slug": "writers", "permission": "push"}]"#)
+            .expect(1)
+            .create();
+        let last_teams = mock("GET", "/repos/me/routed/teams")
+            .match_query(Matcher::UrlEncoded("page".into(), "2".into()))
+            .with_status(200)
+            .with_body(r#"[{"slug": "reviewers", "permission": "pull"}]"#)
+            .expect(1)
+            .create();
+        let grant = mock("PUT", "/orgs/me/teams/reviewers/repos/me/routed")
+            .match_body(Matcher::Json(serde_json::json!({"permission": "push"})))
+            .with_status(204)
+            .expect(1)
+            .create();
+        let missing = mock("GET", "/repos/me/unrouted/contents/.github/CODEOWNERS")
+            .with_status(404)
+            .with_body(r#"{"message": "Not Found"}"#)
+            .expect(1)
+            .create();
+        // `* @me/writers`
+        let unrouted = mock("GET", "/repos/me/unrouted/contents/CODEOWNERS")
+            .with_status(200)
+            .with_body(content("unrouted", "CODEOWNERS", "KiBAbWUvd3JpdGVycwo="))
+            .expect(1)
+            .create();
+        let empty = mock(
+            "GET",
+            Matcher::Regex("^/repos/me/empty/contents/".to_string()),
+        )
+        .with_status(404)
+        .with_body(r#"{"message": "This repository is empty."}"#)
+        .expect(CODEOWNERS_PATHS.len())
+        .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: routed
+      - owner: me
+        name: unrouted
+      - owner: me
+        name: empty
+    steps:
+      - runs:
+          - sync-codeowners-team:
+              org: me
+              team-slug: reviewers
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+        let syncs: Vec<&CodeownersTeamSync> = results[0][0][0]
+            .iter()
+            .map(|outcome| match outcome {
+                Outcome::Succeeded(Response::SyncCodeownersTeam(sync)) => sync,
+                _ => panic!("expected the CODEOWNERS to be synced"),
+            })
+            .collect();
+        assert_eq!(
+            syncs,
+            vec![
+                &CodeownersTeamSync {
+                    codeowners: Some(".github/CODEOWNERS".to_string()),
+                    teams: vec!["me/reviewers".to_string(), "me/writers".to_string()],
+                    previous_permission: Some("pull".to_string()),
+                    granted: true,
+                },
+                &CodeownersTeamSync {
+                    codeowners: Some("CODEOWNERS".to_string()),
+                    teams: vec!["me/writers".to_string()],
+                    previous_permission: None,
+                    granted: false,
+                },
+                &CodeownersTeamSync::default(),
+            ]
+        );
+        routed.assert();
+        first_teams.assert();
+        last_teams.assert();
+        grant.assert();
+        missing.assert();
+        unrouted.assert();
+        empty.assert();
+    }
+
+    #[tokio::test]
+    async fn test_sync_team_repos() {
+        let repository = |name: &str, permission: &str| {
+            let permissions: serde_json::Map<String, serde_json::Value> = TEAM_PERMISSIONS
+                .iter()
+                .map(|known| (known.to_string(), (*known == permission).into()))
+                .collect();
+            serde_json::json!({
+                "name": name,
+                "owner": {"login": "me"},
+                "permissions": permissions
+            })
+        };
+        let route = "/orgs/me/teams/drifting/repos";
+        let first_page = mock("GET", route)
+            .match_query(Matcher::UrlEncoded("per_page".into(), "100".into()))
+            .with_status(200)
+            .with_header(
+                "link",
+                &format!(
+                    r#"<{}{}?per_page=100&page=2>; rel="next""#,
+                    mockito::server_url(),
+                    route
+                ),
+            )
+            .with_body(
+                serde_json::json!([repository("api", "pull"), repository("web", "push")])
+                    .to_string(),
+            )
+            .expect(1)
+            .create();
+        let second_page = mock("GET", route)
+            .match_query(Matcher::UrlEncoded("page".into(), "2".into()))
+            .with_status(200)
+            .with_body(serde_json::json!([repository("legacy", "admin")]).to_string())
+            .expect(1)
+            .create();
+        let grants: Vec<mockito::Mock> = ["api", "worker"]
+            .iter()
+            .map(|name| {
+                mock("PUT", format!("{}/me/{}", route, name).as_str())
+                    .match_body(Matcher::Json(serde_json::json!({"permission": "push"})))
+                    .with_status(204)
+                    .expect(1)
+                    .create()
+            })
+            .collect();
+        let unchanged = mock("PUT", format!("{}/me/web", route).as_str())
+            .expect(0)
+            .create();
+        let removal = mock("DELETE", format!("{}/me/legacy", route).as_str())
+            .with_status(204)
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: api
+      - owner: me
+        name: web
+      - owner: me
+        name: worker
+    steps:
+      - runs:
+          - sync-team-repos:
+              org: me
+              team-slug: drifting
+              permission: push
+              prune: true
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+        match &results[0][0][0][..] {
+            [Outcome::Succeeded(Response::SyncTeamRepos(sync))] => assert_eq!(
+                sync,
+                &TeamReposSync {
+                    added: vec!["me/worker".to_string()],
+                    updated: vec!["me/api".to_string()],
+                    removed: vec!["me/legacy".to_string()],
+                }
+            ),
+            _ => panic!("expected the repositories of the team to be synced"),
+        }
+        first_page.assert();
+        second_page.assert();
+        grants.iter().for_each(mockito::Mock::assert);
+        unchanged.assert();
+        removal.assert();
+
+        assert!(SyncTeamReposOptions {
+            org: "me".to_string(),
+            team_slug: "drifting".to_string(),
+            permission: "write".to_string(),
+            prune: None,
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_workflow() {
+        let runs = |id: u64, created_at: &str, status: &str, conclusion: Option<&str>| {
+            serde_json::json!({
+                "total_count": 1,
+                "workflow_runs": [{
+                    "id": id,
+                    "status": status,
+                    "conclusion": conclusion,
+                    "head_branch": "main",
+                    "created_at": created_at,
+                    "html_url": format!("https://github.com/me/gated/actions/runs/{}", id)
+                }]
+            })
+            .to_string()
+        };
+        let now = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        let new_run =
+            |status: &str, conclusion: Option<&str>| runs(30433642, &now, status, conclusion);
+        let path = || {
+            Matcher::AllOf(vec![
+                Matcher::Regex("^/repos/me/gated/actions/workflows/deploy.yml/runs".to_string()),
+                Matcher::Regex("branch=main".to_string()),
+            ])
+        };
+        // The run before the one just dispatched, until the new one shows up.
+        let previous = mock("GET", path())
+            .with_status(200)
+            .with_body(runs(
+                30433641,
+                "2023-03-01T09:12:44Z",
+                "completed",
+                Some("failure"),
+            ))
+            .expect(1)
+            .create();
+        let queued = mock("GET", path())
+            .with_status(200)
+            .with_body(new_run("queued", None))
+            .expect(1)
+            .create();
+        let in_progress = mock("GET", path())
+            .with_status(200)
+            .with_body(new_run("in_progress", None))
+            .expect(1)
+            .create();
+        let completed = mock("GET", path())
+            .with_status(200)
+            .with_body(new_run("completed", Some("success")))
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: gated
+    steps:
+      - runs:
+          - wait-for-workflow:
+              workflow: deploy.yml
+              git-ref: main
+              timeout-secs: 5
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let octomate = octomate().await.with_settings(Settings {
+            poll_interval: Some(Duration::from_millis(10)),
+            ..Settings::default()
+        });
+        let results = octomate.run_batch(&batch).await;
+        match &results[0][0][0][0] {
+            Outcome::Succeeded(response @ Response::WaitForWorkflow(run)) => {
+                assert_eq!(run["conclusion"], "success");
+                assert_eq!(
+                    response.url().as_deref(),
+                    Some("https://github.com/me/gated/actions/runs/30433642")
+                );
+            }
+            _ => panic!("expected the workflow run to complete"),
+        }
+        previous.assert();
+        queued.assert();
+        in_progress.assert();
+        completed.assert();
+    }
+
+    #[tokio::test]
+    async fn test_create_autolink() {
+        let payload = || {
+            Matcher::Json(serde_json::json!({
+                "key_prefix": "JIRA-",
+                "url_template": "https://jira.example.com/browse/JIRA-<num>",
+                "is_alphanumeric": false
+            }))
+        };
+        let created = mock("POST", "/repos/me/linked/autolinks")
+            .match_body(payload())
+            .with_status(201)
+            .with_body(
+                r#"{
+                    "id": 1,
+                    "key_prefix": "JIRA-",
+                    "url_template": "https://jira.example.com/browse/JIRA-<num>",
+                    "is_alphanumeric": false
+                }"#,
+            )
+            .expect(1)
+            .create();
+        let duplicate = mock("POST", "/repos/me/already-linked/autolinks")
+            .match_body(payload())
+            .with_status(422)
+            .with_body(
+                r#"{
+                    "message": "Validation Failed",
+                    "errors": [{"resource": "KeyPrefix", "code": "already_exists", "field": "key_prefix"}]
+                }"#,
+            )
+            .expect(1)
+            .create();
+        let rejected = mock("POST", "/repos/me/badly-linked/autolinks")
+            .match_body(payload())
+            .with_status(422)
+            .with_body(
+                r#"{
+                    "message": "Validation Failed",
+                    "errors": [{"resource": "KeyPrefix", "code": "invalid", "field": "key_prefix"}]
+                }"#,
+            )
+            .expect(1)
+            .create();
+
+        let batch = |url_template: &str| {
+            Batch::try_from(
+                format!(
+                    r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: linked
+      - owner: me
+        name: already-linked
+      - owner: me
+        name: badly-linked
+    steps:
+      - runs:
+          - create-autolink:
+              key-prefix: JIRA-
+              url-template: {}
+              is-alphanumeric: false
+"#,
+                    url_template
+                )
+                .as_bytes(),
+            )
+        };
+        let results = octomate()
+            .await
+            .run_batch(&batch("https://jira.example.com/browse/JIRA-<num>").unwrap())
+            .await;
+        match &results[0][0][0][..] {
+            [Outcome::Succeeded(Response::CreateAutolink(autolink)), Outcome::Skipped(reason), Outcome::Failed(err)] =>
+            {
+                assert_eq!(autolink["id"], 1);
+                assert_eq!(reason, AUTOLINK_EXISTS);
+                assert!(err.is_validation_error());
+            }
+            _ => panic!("expected the autolink to be created once, and rejected once"),
+        }
+        created.assert();
+        duplicate.assert();
+        rejected.assert();
+
+        assert!(matches!(
+            batch("https://jira.example.com/browse/JIRA"),
+            Err(Error::Validation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_set_repository_info() {
+        let body = r#"{
+            "id": 1,
+            "name": "described",
+            "url": "https://api.github.com/repos/me/described"
+        }"#;
+        let set = mock("PATCH", "/repos/me/described")
+            .match_body(Matcher::Json(serde_json::json!({
+                "description": "The API of the heroes",
+                "homepage": "https://heroes.example.com"
+            })))
+            .with_status(200)
+            .with_body(body)
+            .expect(1)
+            .create();
+        let clear = mock("PATCH", "/repos/me/described")
+            .match_body(Matcher::Json(serde_json::json!({"homepage": ""})))
+            .with_status(200)
+            .with_body(body)
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: described
+    steps:
+      - runs:
+          - set-repository-info:
+              description: The API of the heroes
+              homepage: https://heroes.example.com
+      - runs:
+          - set-repository-info:
+              homepage: ""
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+        for step_results in &results[0] {
+            match &step_results[0][0] {
+                Outcome::Succeeded(Response::UpdateRepositorySettings(repository)) => {
+                    assert_eq!(repository.name, "described")
+                }
+                _ => panic!("expected the repository info to be updated"),
+            }
+        }
+        set.assert();
+        clear.assert();
+
+        assert!(matches!(
+            Batch::try_from(
+                r#"
+version: "1.0"
+jobs:
+  - steps:
+      - runs:
+          - set-repository-info: {}
+"#
+                .as_bytes()
+            ),
+            Err(Error::Validation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_repository_metadata_fetched_once() {
+        let metadata = mock("GET", "/repos/me/cached")
+            .with_status(200)
+            .with_body(
+                r#"{
+                    "id": 1,
+                    "name": "cached",
+                    "url": "https://api.github.com/repos/me/cached",
+                    "default_branch": "main",
+                    "visibility": "internal"
+                }"#,
+            )
+            .expect(1)
+            .create();
+        let _main = mock("GET", "/repos/me/cached/git/ref/heads/main")
+            .with_status(200)
+            .with_body(
+                r#"{
+                    "ref": "refs/heads/main",
+                    "node_id": "MDM6UmVmcmVmcy9oZWFkcy9tYWlu",
+                    "url": "https://api.github.com/repos/me/cached/git/refs/heads/main",
+                    "object": {
+                        "type": "commit",
+                        "sha": "aa218f56b14c9653891f9e74264a383fa43fefbd",
+                        "url": "https://api.github.com/repos/me/cached/git/commits/aa218f56b14c9653891f9e74264a383fa43fefbd"
+                    }
+                }"#,
+            )
+            .create();
+        let _branch = mock("POST", "/repos/me/cached/git/refs")
+            .with_status(201)
+            .with_body(
+                r#"{
+                    "ref": "refs/heads/feature",
+                    "node_id": "MDM6UmVmcmVmcy9oZWFkcy9mZWF0dXJl",
+                    "url": "https://api.github.com/repos/me/cached/git/refs/heads/feature",
+                    "object": {
+                        "type": "commit",
+                        "sha": "aa218f56b14c9653891f9e74264a383fa43fefbd",
+                        "url": "https://api.github.com/repos/me/cached/git/commits/aa218f56b14c9653891f9e74264a383fa43fefbd"
+                    }
+                }"#,
+            )
+            .create();
+        // Already internal, so left as it is.
+        let update = mock("PATCH", "/repos/me/cached").expect(0).create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: cached
+    steps:
+      - runs:
+          - create-branch:
+              name: feature
+          - set-repository-visibility:
+              visibility: internal
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+
+        for outcomes in &results[0][0] {
+            assert!(matches!(outcomes[0], Outcome::Succeeded(_)));
+        }
+        metadata.assert();
+        update.assert();
+    }
+
+    #[tokio::test]
+    async fn test_set_actions_permissions() {
+        let enabled = mock("PUT", "/repos/me/actionable/actions/permissions")
+            .match_body(Matcher::Json(
+                serde_json::json!({"enabled": true, "allowed_actions": "local_only"}),
+            ))
+            .with_status(204)
+            .expect(1)
+            .create();
+        let disabled = mock("PUT", "/repos/me/unactionable/actions/permissions")
+            .match_body(Matcher::Json(serde_json::json!({"enabled": false})))
+            .with_status(204)
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: actionable
+    steps:
+      - runs:
+          - set-actions-permissions:
+              enabled: true
+              allowed-actions: local_only
+  - on-repositories:
+      - owner: me
+        name: unactionable
+    steps:
+      - runs:
+          - set-actions-permissions:
+              enabled: false
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate().await.run_batch(&batch).await;
+
+        for job in &results {
+            assert!(matches!(
+                job[0][0][0],
+                Outcome::Succeeded(Response::SetActionsPermissions(_))
+            ));
+        }
+        enabled.assert();
+        disabled.assert();
+
+        let invalid = SetActionsPermissionsOptions {
+            enabled: true,
+            allowed_actions: Some("none".to_string()),
+        };
+        assert!(matches!(invalid.validate(), Err(Error::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_set_subscription() {
+        let m = mock("PUT", "/repos/me/watched/subscription")
+            .match_body(Matcher::Json(
+                serde_json::json!({"subscribed": true, "ignored": false}),
+            ))
+            .with_status(200)
+            .with_body(r#"{"subscribed": true, "ignored": false, "reason": null}"#)
+            .expect(1)
+            .create();
+
+        let batch = |ignored: bool| {
+            Batch::try_from(
+                format!(
+                    r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: watched
+    steps:
+      - runs:
+          - set-subscription:
+              subscribed: true
+              ignored: {}
+"#,
+                    ignored
+                )
+                .as_bytes(),
+            )
+        };
+        let results = octomate().await.run_batch(&batch(false).unwrap()).await;
+
+        match &results[0][0][0][0] {
+            Outcome::Succeeded(Response::SetSubscription(subscription)) => {
+                assert_eq!(subscription["subscribed"], true)
+            }
+            _ => panic!("expected the subscription to be set"),
+        }
+        m.assert();
+        assert!(matches!(batch(true), Err(Error::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_create_ruleset() {
+        let list = mock("GET", "/repos/me/ruled/rulesets")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_body(r#"[{"id": 7, "name": "tags"}]"#)
+            .expect(1)
+            .create();
+        let create = mock("POST", "/repos/me/ruled/rulesets")
+            .match_body(Matcher::Json(serde_json::json!({
+                "name": "main",
+                "target": "branch",
+                "enforcement": "active",
+                "rules": [
+                    { "type": "deletion" },
+                    {
+                        "type": "pull_request",
+                        "parameters": { "required_approving_review_count": 1 }
+                    }
+                ],
+                "conditions": {
+                    "ref_name": { "include": ["~DEFAULT_BRANCH"], "exclude": [] }
+                }
+            })))
+            .with_status(201)
+            .with_body(r#"{"id": 42, "name": "main"}"#)
+            .expect(1)
+            .create();
+
+        let batch = |enforcement: &str| {
+            Batch::try_from(
+                format!(
+                    r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: ruled
+    steps:
+      - runs:
+          - create-ruleset:
+              name: main
+              target: branch
+              enforcement: {}
+              rules:
+                - type: deletion
+                - type: pull_request
+                  parameters:
+                    required_approving_review_count: 1
+              conditions:
+                ref_name:
+                  include: ["~DEFAULT_BRANCH"]
+                  exclude: []
+"#,
+                    enforcement
+                )
+                .as_bytes(),
+            )
+        };
+        let results = octomate().await.run_batch(&batch("active").unwrap()).await;
+
+        match &results[0][0][0][0] {
+            Outcome::Succeeded(Response::CreateRuleset(ruleset)) => assert_eq!(ruleset["id"], 42),
+            _ => panic!("expected the ruleset to be created"),
+        }
+        list.assert();
+        create.assert();
+
+        assert!(matches!(batch("enforced"), Err(Error::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_create_tag_ruleset() {
+        let route = "/repos/me/tagged/rulesets";
+        let first_page = mock("GET", route)
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("includes_parents".into(), "false".into()),
+                Matcher::UrlEncoded("per_page".into(), "100".into()),
+            ]))
+            .with_status(200)
+            .with_header(
+                "link",
+                &format!(
+                    r#"<{}{}?includes_parents=false&per_page=100&page=2>; rel="next""#,
+                    mockito::server_url(),
+                    route
+                ),
+            )
+            .with_body(r#"[{"id": 3, "name": "releases", "source_type": "Organization"}]"#)
+            .expect(1)
+            .create();
+        let second_page = mock("GET", route)
+            .match_query(Matcher::UrlEncoded("page".into(), "2".into()))
+            .with_status(200)
+            .with_body(r#"[{"id": 7, "name": "releases", "source_type": "Repository"}]"#)
+            .expect(1)
+            .create();
+        let update = mock("PUT", "/repos/me/tagged/rulesets/7")
+            .match_body(Matcher::Json(serde_json::json!({
+                "name": "releases",
+                "target": "tag",
+                "enforcement": "active",
+                "rules": [
+                    { "type": "update" },
+                    { "type": "deletion" },
+                    {
+                        "type": "tag_name_pattern",
+                        "parameters": { "operator": "regex", "pattern": "^v\\d+\\.\\d+\\.\\d+$" }
+                    }
+                ],
+                "conditions": {
+                    "ref_name": { "include": ["refs/tags/v*"], "exclude": [] }
+                }
+            })))
+            .with_status(200)
+            .with_body(r#"{"id": 7, "name": "releases"}"#)
+            .expect(1)
+            .create();
+
+        let batch = |rule: &str| {
+            Batch::try_from(
+                format!(
+                    r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: tagged
+    steps:
+      - runs:
+          - create-ruleset:
+              name: releases
+              target: tag
+              enforcement: active
+              rules:
+                - type: update
+                - type: deletion
+                - {}
+              conditions:
+                ref_name:
+                  include: ["refs/tags/v*"]
+                  exclude: []
+"#,
+                    rule
+                )
+                .as_bytes(),
+            )
+        };
+        let tag_name_pattern = r#"type: tag_name_pattern
+                  parameters:
+                    operator: regex
+                    pattern: '^v\d+\.\d+\.\d+$'"#;
+        let results = octomate()
+            .await
+            .run_batch(&batch(tag_name_pattern).unwrap())
+            .await;
+
+        assert!(matches!(
+            results[0][0][0][0],
+            Outcome::Succeeded(Response::CreateRuleset(_))
+        ));
+        first_page.assert();
+        second_page.assert();
+        update.assert();
+
+        assert!(matches!(
+            batch("type: pull_request"),
+            Err(Error::Validation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_retry_only_idempotent_commands() {
+        let issue = mock("POST", "/repos/me/flaky/issues")
+            .with_status(500)
+            .with_body(r#"{"message": "Server Error"}"#)
+            .expect(1)
+            .create();
+        let autolink = mock("POST", "/repos/me/flaky/autolinks")
+            .with_status(502)
+            .with_body(r#"{"message": "Server Error"}"#)
+            .expect(1)
+            .create();
+        let label = mock("DELETE", "/repos/me/flaky/labels/bug")
+            .with_status(500)
+            .with_body(r#"{"message": "Server Error"}"#)
+            .expect(3)
+            .create();
+        // Nothing was created behind a server error without a body.
+        let proxied = mock("POST", "/repos/me/proxied/autolinks")
+            .with_status(503)
+            .expect(3)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: flaky
+    steps:
+      - runs:
+          - create-issue:
+              title: "Created once"
+              body: "Even if Github fails after creating it"
+          - create-autolink:
+              key-prefix: "TICKET-"
+              url-template: "https://tracker.example.com/TICKET-<num>"
+          - delete-label:
+              name: bug
+  - on-repositories:
+      - owner: me
+        name: proxied
+    steps:
+      - runs:
+          - create-autolink:
+              key-prefix: "TICKET-"
+              url-template: "https://tracker.example.com/TICKET-<num>"
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let octomate = octomate().await.with_settings(Settings {
+            retry: RetryPolicy {
+                base: Duration::from_millis(1),
+                ..RetryPolicy::default()
+            },
+            ..Settings::default()
+        });
+        let results = octomate.run_batch(&batch).await;
+
+        for outcomes in results.iter().flatten().flatten() {
+            assert!(matches!(outcomes[0], Outcome::Failed(_)));
+        }
+        issue.assert();
+        autolink.assert();
+        label.assert();
+        proxied.assert();
+    }
+
+    #[tokio::test]
+    async fn test_step_retries() {
+        let retried = mock("DELETE", "/repos/me/eventual/labels/bug")
+            .with_status(500)
+            .with_body(r#"{"message": "Server Error"}"#)
+            .expect(5)
+            .create();
+        let default = mock("DELETE", "/repos/me/eventual/labels/wontfix")
+            .with_status(500)
+            .with_body(r#"{"message": "Server Error"}"#)
+            .expect(2)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: eventual
+    steps:
+      - retries: 4
+        runs:
+          - delete-label:
+              name: bug
+      - runs:
+          - delete-label:
+              name: wontfix
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let octomate = octomate().await.with_settings(Settings {
+            retry: RetryPolicy {
+                max_attempts: 2,
+                base: Duration::from_millis(1),
+                ..RetryPolicy::default()
+            },
+            ..Settings::default()
+        });
+        octomate.run_batch(&batch).await;
+
+        retried.assert();
+        default.assert();
+    }
+
+    #[tokio::test]
+    async fn test_step_retries_missing() {
+        let missing = mock("GET", "/repos/me/lagging/milestones")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_body("[]")
+            .expect(1)
+            .create();
+        let found = mock("GET", "/repos/me/lagging/milestones")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_body(serde_json::json!([milestone("lagging", 2, "v1")]).to_string())
+            .expect(2)
+            .create();
+        let not_found = mock("PATCH", "/repos/me/lagging/issues/42")
+            .with_status(404)
+            .with_body(r#"{"message": "Not Found"}"#)
+            .expect(1)
+            .create();
+        let update = mock("PATCH", "/repos/me/lagging/issues/42")
+            .match_body(Matcher::Json(serde_json::json!({"milestone": 2})))
+            .with_status(200)
+            .with_body(ISSUE)
+            .expect(1)
+            .create();
+        let without_retries = mock("PATCH", "/repos/me/lagging/issues/43")
+            .with_status(404)
+            .with_body(r#"{"message": "Not Found"}"#)
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: lagging
+    steps:
+      - retries: 5
+        runs:
+          - set-issue-milestone:
+              number: 42
+              milestone-title: v1
+      - runs:
+          - set-issue-milestone:
+              number: 43
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let octomate = octomate().await.with_settings(Settings {
+            retry: RetryPolicy {
+                base: Duration::from_millis(1),
+                ..RetryPolicy::default()
+            },
+            ..Settings::default()
+        });
+        let results = octomate.run_batch(&batch).await;
+
+        assert!(matches!(
+            results[0][0][0][0],
+            Outcome::Succeeded(Response::UpdateIssue(_))
+        ));
+        assert!(matches!(&results[0][1][0][0], Outcome::Failed(err) if err.is_not_found()));
+        missing.assert();
+        found.assert();
+        not_found.assert();
+        update.assert();
+        without_retries.assert();
+    }
+
+    #[tokio::test]
+    async fn test_on_command_complete() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::{Arc, Mutex};
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+name: hooked
+jobs:
+  - name: labels
+    on-repositories:
+      - owner: me
+        name: repo1
+      - owner: me
+        name: repo2
+    steps:
+      - runs:
+          - delete-label:
+              name: bug
+          - delete-label:
+              name: wontfix
+          - create-gist:
+              title: notes.md
+              content: "Some notes"
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+        let completed = Arc::new(Mutex::new(vec![]));
+        let octomate = {
+            let (count, completed) = (count.clone(), completed.clone());
+            octomate()
+                .await
+                .with_settings(Settings {
+                    dry_run: true,
+                    ..Settings::default()
+                })
+                .on_command_complete(move |info, outcome| {
+                    assert!(matches!(outcome, Outcome::DryRun(_)));
+                    assert_eq!(info.batch, "hooked");
+                    assert_eq!(info.job, Some("labels"));
+                    count.fetch_add(1, Ordering::SeqCst);
+                    completed.lock().unwrap().push(format!(
+                        "{} {}",
+                        info.command.name(),
+                        info.repository
+                            .map(|repository| repository.name.as_str())
+                            .unwrap_or("-")
+                    ));
+                })
+        };
+        octomate.run_batch(&batch).await;
+
+        assert_eq!(count.load(Ordering::SeqCst), 5);
+        let mut completed = completed.lock().unwrap().clone();
+        completed.sort();
+        assert_eq!(
+            completed,
+            vec![
+                "create-gist -",
+                "delete-label repo1",
+                "delete-label repo1",
+                "delete-label repo2",
+                "delete-label repo2",
+            ]
+        );
+    }
 }
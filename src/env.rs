@@ -0,0 +1,182 @@
+use crate::error::Error;
+use crate::octomate::Batch;
+use serde_yaml::Value;
+use std::collections::HashMap;
+
+/// Variables of an `env` block of the batch, a job or a step.
+pub type Env = HashMap<String, String>;
+
+/// Expand the `${NAME}` references of the commands of the batch.
+/// A reference resolves to the `env` block of the step, else the ones of its job and the batch,
+/// else the environment of the process.
+pub fn resolve(batch: &mut Batch) -> Result<(), Error> {
+    let batch_env = scope(&Env::new(), batch.env.as_ref())?;
+    for job in &mut batch.jobs {
+        let job_env = scope(&batch_env, job.env.as_ref())?;
+        for step in job.all_steps_mut() {
+            let step_env = scope(&job_env, step.env.as_ref())?;
+            for command in &mut step.runs {
+                let mut value = serde_yaml::to_value(&*command)?;
+                expand_value(&mut value, &step_env)?;
+                *command = serde_yaml::from_value(value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The variables of `outer` overridden by the ones of `env`, themselves expanded against `outer`.
+fn scope(outer: &Env, env: Option<&Env>) -> Result<Env, Error> {
+    let mut merged = outer.clone();
+    for (name, value) in env.into_iter().flatten() {
+        merged.insert(name.clone(), expand(value, outer)?);
+    }
+    Ok(merged)
+}
+
+/// Replace the `${NAME}` references of `text`, an unknown variable being an error.
+/// Anything else starting with `${` is left as it is.
+pub fn expand(text: &str, env: &Env) -> Result<String, Error> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let reference = &rest[start + 2..];
+        let name = reference
+            .find('}')
+            .map(|end| &reference[..end])
+            .filter(|name| is_variable_name(name));
+        match name {
+            Some(name) => {
+                let value = env
+                    .get(name)
+                    .cloned()
+                    .or_else(|| std::env::var(name).ok())
+                    .ok_or_else(|| Error::Validation(format!("undefined variable {}", name)))?;
+                expanded.push_str(&value);
+                rest = &reference[name.len() + 1..];
+            }
+            None => {
+                expanded.push_str("${");
+                rest = reference;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Whether `text` is nothing but a `${NAME}` reference.
+pub fn is_reference(text: &str) -> bool {
+    text.strip_prefix("${")
+        .and_then(|reference| reference.strip_suffix('}'))
+        .is_some_and(is_variable_name)
+}
+
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Expand the strings found anywhere in `value`.
+fn expand_value(value: &mut Value, env: &Env) -> Result<(), Error> {
+    match value {
+        Value::String(text) => *text = expand(text, env)?,
+        Value::Sequence(items) => {
+            for item in items {
+                expand_value(item, env)?;
+            }
+        }
+        Value::Mapping(mapping) => {
+            for (_, item) in mapping.iter_mut() {
+                expand_value(item, env)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::Command;
+
+    #[test]
+    fn test_step_env_overrides_job_env() {
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+env:
+  TEAM: avengers
+  COLOR: "000000"
+jobs:
+  - name: labels
+    env:
+      PREFIX: team-${TEAM}
+      COLOR: ffffff
+    on-repositories: []
+    steps:
+      - name: with step env
+        env:
+          PREFIX: step-${TEAM}
+        runs:
+          - create-label:
+              name: ${PREFIX}
+              color: ${COLOR}
+              description: "${TEAM} costs $5, ${not a variable}"
+      - name: with job env
+        runs:
+          - create-label:
+              name: ${PREFIX}
+              color: ${COLOR}
+              description: ""
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let labels: Vec<(String, String, String)> = batch.jobs[0]
+            .steps
+            .iter()
+            .map(|step| match &step.runs[0] {
+                Command::CreateLabel(labels) => (
+                    labels.labels()[0].name.clone(),
+                    labels.labels()[0].color.clone(),
+                    labels.labels()[0].description.clone(),
+                ),
+                _ => panic!("expected a create-label command"),
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                (
+                    "step-avengers".to_string(),
+                    "ffffff".to_string(),
+                    "avengers costs $5, ${not a variable}".to_string()
+                ),
+                (
+                    "team-avengers".to_string(),
+                    "ffffff".to_string(),
+                    "".to_string()
+                ),
+            ]
+        );
+
+        let undefined = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories: []
+    steps:
+      - runs:
+          - delete-label:
+              name: ${OCTOMATE_UNDEFINED_VARIABLE}
+"#
+            .as_bytes(),
+        );
+        assert!(matches!(undefined, Err(Error::Validation(_))));
+    }
+}
@@ -1,9 +1,145 @@
+use crate::octomate::Repository;
+
 #[derive(Debug)]
 pub enum Error {
     IO(tokio::io::Error),
     SerdeJson(serde_json::Error),
     SerdeYaml(serde_yaml::Error),
     Octocrab(octocrab::Error),
+    /// An error response of Github, with its status.
+    Github {
+        status: u16,
+        message: String,
+        /// The `errors` detailing a validation failure.
+        errors: Vec<serde_json::Value>,
+        /// Whether the response had no body at all.
+        empty: bool,
+    },
+    Hyper(hyper::Error),
+    Http(hyper::http::Error),
+    MissingDefaultBranch(Repository),
+    MissingNodeId(Repository),
+    UnsupportedReference(String),
+    Notification(u16),
+    Timeout(std::time::Duration),
+    Validation(String),
+    GraphQL(Vec<String>),
+    InvalidAssignees {
+        repository: Repository,
+        assignees: Vec<String>,
+    },
+    UnexpectedRepositoryCount {
+        expected: usize,
+        actual: usize,
+    },
+    NoRepositoriesTargeted,
+    DuplicateJobName {
+        name: String,
+    },
+    Download {
+        url: String,
+        reason: String,
+    },
+    NotATerminal,
+    InvalidHeader(String),
+    UnknownPlaceholder(String),
+    MissingTemplate {
+        repository: Repository,
+        template: String,
+    },
+    MilestoneExists {
+        repository: Repository,
+        title: String,
+    },
+    MissingMilestone {
+        repository: Repository,
+        title: String,
+    },
+    MissingPullRequest {
+        repository: Repository,
+        number: u64,
+    },
+    UnknownField {
+        field: String,
+        location: String,
+    },
+}
+
+impl Error {
+    /// The HTTP status code of the response behind the error, when known.
+    ///
+    /// octocrab 0.18 doesn't keep the status of the Github error responses,
+    /// so for the requests not sent through `rest` it's inferred from the message
+    /// Github sends along with them.
+    pub fn status_code(&self) -> Option<u16> {
+        match self {
+            Error::Github { status, .. } => Some(*status),
+            Error::Octocrab(octocrab::Error::GitHub { source, .. }) => {
+                let message = source.message.to_lowercase();
+                if message.contains("rate limit") {
+                    Some(403)
+                } else {
+                    match message.as_str() {
+                        "bad credentials" | "requires authentication" => Some(401),
+                        "not found" | "branch not protected" => Some(404),
+                        "validation failed" => Some(422),
+                        "server error" => Some(500),
+                        _ => None,
+                    }
+                }
+            }
+            Error::Octocrab(octocrab::Error::Http { source, .. }) => {
+                source.status().map(|status| status.as_u16())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.status_code() == Some(404)
+    }
+
+    pub fn is_validation_error(&self) -> bool {
+        self.status_code() == Some(422)
+    }
+
+    /// Whether Github refused to create something because it exists already,
+    /// rather than because of another validation failure.
+    pub fn is_already_exists(&self) -> bool {
+        let errors = match self {
+            Error::Github { errors, .. } => errors.as_slice(),
+            Error::Octocrab(octocrab::Error::GitHub { source, .. }) => {
+                source.errors.as_deref().unwrap_or_default()
+            }
+            _ => &[],
+        };
+        self.is_validation_error() && errors.iter().any(|error| error["code"] == "already_exists")
+    }
+
+    pub fn is_rate_limited(&self) -> bool {
+        match self {
+            Error::Octocrab(octocrab::Error::GitHub { source, .. }) => {
+                source.message.to_lowercase().contains("rate limit")
+            }
+            Error::Github { message, .. } if message.to_lowercase().contains("rate limit") => true,
+            _ => self.status_code() == Some(429),
+        }
+    }
+
+    /// Whether a server error came without any body, like the ones of a proxy in front
+    /// of Github, so nothing was created.
+    pub fn is_empty_server_error(&self) -> bool {
+        matches!(self, Error::Github { status, empty: true, .. } if *status >= 500)
+    }
+
+    /// Whether the connection failed, so the request never reached Github.
+    pub fn is_connect(&self) -> bool {
+        match self {
+            Error::Octocrab(octocrab::Error::Http { source, .. }) => source.is_connect(),
+            Error::Hyper(err) => err.is_connect(),
+            _ => false,
+        }
+    }
 }
 
 impl From<tokio::io::Error> for Error {
@@ -30,10 +166,184 @@ impl From<octocrab::Error> for Error {
     }
 }
 
+impl From<hyper::Error> for Error {
+    fn from(err: hyper::Error) -> Self {
+        Error::Hyper(err)
+    }
+}
+
+impl From<hyper::http::Error> for Error {
+    fn from(err: hyper::http::Error) -> Self {
+        Error::Http(err)
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("Error {}", self))
+        match self {
+            Error::IO(err) => write!(f, "IO error: {}", err),
+            Error::SerdeJson(err) => write!(f, "JSON error: {}", err),
+            Error::SerdeYaml(err) => write!(f, "YAML error: {}", err),
+            Error::Octocrab(err) => write!(f, "Github API error: {}", err),
+            Error::Github {
+                status, message, ..
+            } => write!(f, "Github API error: {} ({})", message, status),
+            Error::Hyper(err) => write!(f, "HTTP error: {}", err),
+            Error::Http(err) => write!(f, "HTTP error: {}", err),
+            Error::MissingDefaultBranch(repository) => write!(
+                f,
+                "No default branch found for {}/{}",
+                repository.owner, repository.name
+            ),
+            Error::MissingNodeId(repository) => write!(
+                f,
+                "No node id found for {}/{}",
+                repository.owner, repository.name
+            ),
+            Error::Validation(reason) => write!(f, "Invalid batch: {}", reason),
+            Error::GraphQL(messages) => write!(f, "GraphQL error: {}", messages.join(", ")),
+            Error::InvalidAssignees {
+                repository,
+                assignees,
+            } => write!(
+                f,
+                "Invalid assignees for {}/{}: {}",
+                repository.owner,
+                repository.name,
+                assignees.join(", ")
+            ),
+            Error::Timeout(duration) => write!(f, "Timed out after {:?}", duration),
+            Error::UnexpectedRepositoryCount { expected, actual } => write!(
+                f,
+                "The batch targets {} repositories instead of {}",
+                actual, expected
+            ),
+            Error::NoRepositoriesTargeted => write!(f, "The batch targets no repository"),
+            Error::DuplicateJobName { name } => {
+                write!(f, "Several jobs are named {}", name)
+            }
+            Error::Download { url, reason } => write!(f, "Unable to download {}: {}", url, reason),
+            Error::MissingTemplate {
+                repository,
+                template,
+            } => write!(
+                f,
+                "No issue template {} found in {}/{}",
+                template, repository.owner, repository.name
+            ),
+            Error::MilestoneExists { repository, title } => write!(
+                f,
+                "Milestone {} already exists in {}/{}",
+                title, repository.owner, repository.name
+            ),
+            Error::MissingMilestone { repository, title } => write!(
+                f,
+                "No milestone {} found in {}/{}",
+                title, repository.owner, repository.name
+            ),
+            Error::MissingPullRequest { repository, number } => write!(
+                f,
+                "No pull request #{} found in {}/{}",
+                number, repository.owner, repository.name
+            ),
+            Error::UnknownField { field, location } => {
+                write!(f, "Unknown field {} in {}", field, location)
+            }
+            Error::InvalidHeader(header) => {
+                write!(f, "Invalid header {:?}, expected Name: Value", header)
+            }
+            Error::UnknownPlaceholder(placeholder) => write!(
+                f,
+                "Unknown placeholder {{{}}} in the output template, expected one of {}",
+                placeholder,
+                crate::output::PLACEHOLDERS.join(", ")
+            ),
+            Error::NotATerminal => write!(f, "Interactive mode requires a terminal"),
+            Error::Notification(status) => {
+                write!(f, "Notification failed with status {}", status)
+            }
+            Error::UnsupportedReference(reference) => {
+                write!(f, "Unsupported git object for reference {}", reference)
+            }
+        }
     }
 }
 
 impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mockito::mock;
+
+    async fn error_for(status: usize, body: &str) -> Error {
+        let _m = mock("GET", "/repos/me/repo1")
+            .with_status(status)
+            .with_body(body)
+            .create();
+        let octocrab = octocrab::Octocrab::builder()
+            .base_url(mockito::server_url())
+            .unwrap()
+            .build()
+            .unwrap();
+        Error::from(octocrab.repos("me", "repo1").get().await.unwrap_err())
+    }
+
+    #[tokio::test]
+    async fn test_classify_octocrab_errors() {
+        let not_found = error_for(404, r#"{"message": "Not Found"}"#).await;
+        assert_eq!(not_found.status_code(), Some(404));
+        assert!(not_found.is_not_found());
+        assert!(!not_found.is_validation_error());
+        assert!(!not_found.is_rate_limited());
+
+        let validation = error_for(
+            422,
+            r#"{"message": "Validation Failed", "errors": [{"code": "already_exists"}]}"#,
+        )
+        .await;
+        assert_eq!(validation.status_code(), Some(422));
+        assert!(validation.is_validation_error());
+        assert!(validation.is_already_exists());
+        assert!(!validation.is_not_found());
+        assert!(!not_found.is_already_exists());
+
+        let rate_limited = error_for(
+            403,
+            r#"{"message": "API rate limit exceeded for user ID 1."}"#,
+        )
+        .await;
+        assert_eq!(rate_limited.status_code(), Some(403));
+        assert!(rate_limited.is_rate_limited());
+
+        let unknown = error_for(418, r#"{"message": "I'm a teapot"}"#).await;
+        assert_eq!(unknown.status_code(), None);
+        assert!(!unknown.is_not_found());
+
+        assert_eq!(Error::Validation("invalid".to_string()).status_code(), None);
+    }
+
+    #[tokio::test]
+    async fn test_keep_status_of_rest_errors() {
+        let _m = mock("GET", "/repos/me/unavailable")
+            .with_status(503)
+            .with_header("content-type", "text/html")
+            .with_body("<html><body><h1>503 Service Unavailable</h1></body></html>")
+            .create();
+        let octocrab = octocrab::Octocrab::builder()
+            .base_url(mockito::server_url())
+            .unwrap()
+            .build()
+            .unwrap();
+        let err =
+            crate::rest::get::<serde_json::Value>(&octocrab, "repos/me/unavailable", None::<&()>)
+                .await
+                .unwrap_err();
+        assert_eq!(err.status_code(), Some(503));
+        assert_eq!(
+            err.to_string(),
+            "Github API error: Service Unavailable (503)"
+        );
+        assert!(!err.is_rate_limited());
+    }
+}
@@ -0,0 +1,29 @@
+use crate::error::Error;
+use crate::rest;
+use octocrab::Octocrab;
+use serde_json::Value;
+
+/// Send a GraphQL query with its variables and return the `data` of the response.
+/// The errors reported in the body of the response are turned into an `Error::GraphQL`.
+pub async fn query(octocrab: &Octocrab, query: &str, variables: Value) -> Result<Value, Error> {
+    let body = serde_json::json!({
+        "query": query,
+        "variables": variables,
+    });
+    let mut response: Value = rest::post(octocrab, "graphql", &body).await?;
+
+    if let Some(errors) = response.get("errors").and_then(Value::as_array) {
+        let messages = errors
+            .iter()
+            .map(|error| {
+                error
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("unknown error")
+                    .to_string()
+            })
+            .collect();
+        return Err(Error::GraphQL(messages));
+    }
+    Ok(response["data"].take())
+}
@@ -0,0 +1,250 @@
+use crate::error::Error;
+use crate::octomate::{Job, Step};
+use std::io::{BufRead, BufReader, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// What to do with the next step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    Proceed,
+    Skip,
+    Abort,
+}
+
+/// Ask before each step whether to run it, skip it or abort the batch.
+/// Once aborted, every following step is aborted without asking.
+pub struct Stepper {
+    input: Mutex<Box<dyn BufRead + Send>>,
+    output: Mutex<Box<dyn Write + Send>>,
+    aborted: AtomicBool,
+}
+
+impl std::fmt::Debug for Stepper {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Stepper")
+            .field("aborted", &self.aborted)
+            .finish()
+    }
+}
+
+impl Stepper {
+    pub fn new(input: impl BufRead + Send + 'static, output: impl Write + Send + 'static) -> Self {
+        Self {
+            input: Mutex::new(Box::new(input)),
+            output: Mutex::new(Box::new(output)),
+            aborted: AtomicBool::new(false),
+        }
+    }
+
+    /// Prompt on the terminal, failing if stdin or stdout isn't one.
+    pub fn from_tty() -> Result<Self, Error> {
+        if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+            return Err(Error::NotATerminal);
+        }
+        Ok(Self::new(
+            BufReader::new(std::io::stdin()),
+            std::io::stdout(),
+        ))
+    }
+
+    /// Ask what to do with `step` of `job`, until a valid answer is given.
+    /// The end of the input aborts the batch.
+    pub fn ask(&self, job: &Job, step: &Step) -> Result<Decision, Error> {
+        if self.aborted.load(Ordering::SeqCst) {
+            return Ok(Decision::Abort);
+        }
+        let repositories: Vec<String> = job
+            .on_repositories
+            .iter()
+            .map(|repository| format!("{}/{}", repository.owner, repository.name))
+            .collect();
+
+        let mut input = self.input.lock().unwrap();
+        let mut output = self.output.lock().unwrap();
+        let decision = loop {
+            write!(
+                output,
+                "Step {} of job {} on {}. [p]roceed, [s]kip or [a]bort? ",
+                step.name.as_deref().unwrap_or("UNAMED"),
+                job.name.as_deref().unwrap_or("UNAMED"),
+                repositories.join(", ")
+            )?;
+            output.flush()?;
+
+            let mut answer = String::new();
+            if input.read_line(&mut answer)? == 0 {
+                break Decision::Abort;
+            }
+            match answer.trim().to_lowercase().as_str() {
+                "p" | "proceed" => break Decision::Proceed,
+                "s" | "skip" => break Decision::Skip,
+                "a" | "abort" => break Decision::Abort,
+                _ => continue,
+            }
+        };
+        if decision == Decision::Abort {
+            self.aborted.store(true, Ordering::SeqCst);
+        }
+        Ok(decision)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::{Outcome, Response};
+    use crate::octomate::{Batch, ClientOptions, Octomate, Settings};
+    use mockito::mock;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_interactive_steps() {
+        let m = mock("POST", "/repos/me/repo1/labels")
+            .with_status(201)
+            .with_body(
+                r#"{
+                    "id": 208045946,
+                    "node_id": "MDU6TGFiZWwyMDgwNDU5NDY=",
+                    "url": "https://api.github.com/repos/me/repo1/labels/bug",
+                    "name": "bug",
+                    "description": "Something isn't working",
+                    "color": "f29513",
+                    "default": true
+                }"#,
+            )
+            .expect(1)
+            .create();
+
+        let step = r#"
+      - name: "label"
+        runs:
+          - create-label:
+              name: "bug"
+              color: "f29513"
+              description: "Something isn't working""#;
+        let batch = Batch::try_from(
+            format!(
+                r#"
+version: "1.0"
+jobs:
+  - name: "first"
+    on-repositories:
+      - owner: me
+        name: repo1
+    steps:{step}{step}{step}
+  - name: "second"
+    on-repositories:
+      - owner: me
+        name: repo1
+    steps:{step}
+"#
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let stepper = Stepper::new("maybe\np\nskip\na\n".as_bytes(), std::io::sink());
+        let octomate = Octomate::with_options(
+            "test",
+            &ClientOptions {
+                base_url: Some(mockito::server_url()),
+                ..ClientOptions::default()
+            },
+        )
+        .await
+        .unwrap()
+        .with_settings(Settings {
+            interactive: Some(Arc::new(stepper)),
+            ..Settings::default()
+        });
+        let results = octomate.run_batch(&batch).await;
+
+        let outcomes: Vec<&str> = results
+            .iter()
+            .flatten()
+            .flatten()
+            .flatten()
+            .map(|outcome| match outcome {
+                Outcome::Succeeded(Response::CreateLabel(_)) => "succeeded",
+                Outcome::Skipped(reason) => reason.as_str(),
+                _ => panic!("unexpected outcome"),
+            })
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                "succeeded",
+                "step skipped",
+                "batch aborted",
+                "batch aborted"
+            ]
+        );
+        m.assert();
+    }
+
+    /// Input answering after a while, like someone reading the prompt first.
+    struct SlowInput(&'static [u8]);
+
+    impl std::io::Read for SlowInput {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            self.0.read(buf)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prompt_leaves_the_runtime_running() {
+        use std::sync::atomic::AtomicUsize;
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - name: "first"
+    on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - delete-label:
+              name: "bug"
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let stepper = Stepper::new(BufReader::new(SlowInput(b"p\n")), std::io::sink());
+        let octomate = Octomate::new("test")
+            .await
+            .unwrap()
+            .with_settings(Settings {
+                dry_run: true,
+                interactive: Some(Arc::new(stepper)),
+                ..Settings::default()
+            });
+
+        // The test runtime has a single thread, which a blocking prompt would hold.
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let ticker = {
+            let ticks = ticks.clone();
+            tokio::spawn(async move {
+                loop {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                }
+            })
+        };
+        let results = octomate.run_batch(&batch).await;
+        ticker.abort();
+
+        assert!(matches!(results[0][0][0][0], Outcome::DryRun(_)));
+        assert!(ticks.load(Ordering::SeqCst) > 1);
+    }
+
+    #[test]
+    fn test_end_of_input_aborts() {
+        let stepper = Stepper::new("".as_bytes(), std::io::sink());
+        let (job, step) = (Job::default(), Step::default());
+        assert_eq!(stepper.ask(&job, &step).unwrap(), Decision::Abort);
+    }
+}
@@ -1,8 +1,165 @@
 use crate::error::Error;
-use std::path::Path;
+use hyper::body::HttpBody;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Request};
+use hyper_tls::HttpsConnector;
+use std::path::{Path, PathBuf};
 use tokio::fs;
 
+/// Maximum size of a batch downloaded from a URL.
+const MAX_DOWNLOAD_SIZE: usize = 1024 * 1024;
+
+/// Schemes of the URLs a batch can be downloaded from.
+const DOWNLOAD_SCHEMES: [&str; 2] = ["http://", "https://"];
+
+/// The client of the requests outside the Github API: downloads, notifications and the recorder.
+/// One is built per run and passed around, so they all reuse the same connections.
+pub type HttpClient = Client<HttpsConnector<HttpConnector>>;
+
+pub fn http_client() -> HttpClient {
+    Client::builder().build(HttpsConnector::new())
+}
+
 pub async fn read_file(path: impl AsRef<Path>) -> Result<Vec<u8>, Error> {
     let bytes = fs::read(&path).await?;
     Ok(bytes)
 }
+
+/// Read a batch from a local path, or download it when `location` is an HTTP(S) URL.
+pub async fn read_batch(http: &HttpClient, location: impl AsRef<Path>) -> Result<Vec<u8>, Error> {
+    match location.as_ref().to_str() {
+        Some(url) if url.contains("://") => download(http, url, MAX_DOWNLOAD_SIZE).await,
+        _ => read_file(location).await,
+    }
+}
+
+/// GET `url`, failing if the response body is bigger than `max_size` bytes.
+async fn download(http: &HttpClient, url: &str, max_size: usize) -> Result<Vec<u8>, Error> {
+    let error = |reason: String| Error::Download {
+        url: url.to_string(),
+        reason,
+    };
+    if !DOWNLOAD_SCHEMES
+        .iter()
+        .any(|scheme| url.to_lowercase().starts_with(scheme))
+    {
+        return Err(error("only http and https URLs are supported".to_string()));
+    }
+
+    let request = Request::get(url).body(Body::empty())?;
+    let response = http.request(request).await?;
+    if !response.status().is_success() {
+        return Err(error(format!("status {}", response.status().as_u16())));
+    }
+
+    let too_large = || error(format!("larger than {} bytes", max_size));
+    if response
+        .body()
+        .size_hint()
+        .upper()
+        .is_some_and(|size| size > max_size as u64)
+    {
+        return Err(too_large());
+    }
+    let mut body = response.into_body();
+    let mut bytes = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > max_size {
+            return Err(too_large());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Extensions of the files considered as batch files.
+const BATCH_EXTENSIONS: [&str; 3] = ["yml", "yaml", "json"];
+
+/// List the batch files of `dir`, sorted lexicographically.
+pub async fn list_batch_files(dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, Error> {
+    let mut batch_files = vec![];
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let is_batch_file = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| BATCH_EXTENSIONS.contains(&extension));
+        if is_batch_file && entry.file_type().await?.is_file() {
+            batch_files.push(path);
+        }
+    }
+    batch_files.sort();
+    Ok(batch_files)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::octomate::{Batch, UnknownFields};
+
+    #[tokio::test]
+    async fn test_list_batch_files() {
+        let dir = std::env::temp_dir().join(format!("octomate-batch-dir-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("README.md"), "Not a batch").unwrap();
+        std::fs::write(
+            dir.join("02-second.json"),
+            r#"{"version": "1.0", "name": "second", "jobs": []}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("01-first.yml"),
+            "version: \"1.0\"\nname: first\njobs: []\n",
+        )
+        .unwrap();
+
+        let batch_files = list_batch_files(&dir).await.unwrap();
+        assert_eq!(
+            batch_files,
+            vec![dir.join("01-first.yml"), dir.join("02-second.json")]
+        );
+
+        let http = http_client();
+        let mut names = vec![];
+        for batch_file in &batch_files {
+            names.push(
+                Batch::from_file(&http, batch_file, UnknownFields::Deny)
+                    .await
+                    .unwrap()
+                    .display_name(),
+            );
+        }
+        assert_eq!(names, vec!["first", "second"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_read_batch_from_url() {
+        let m = mockito::mock("GET", "/batches/labels.yml")
+            .with_status(200)
+            .with_body("version: \"1.0\"\nname: remote\njobs: []\n")
+            .expect(1)
+            .create();
+
+        let http = http_client();
+        let url = format!("{}/batches/labels.yml", mockito::server_url());
+        let batch = Batch::from_file(&http, &url, UnknownFields::Deny)
+            .await
+            .unwrap();
+        assert_eq!(batch.display_name(), "remote");
+        m.assert();
+
+        assert!(matches!(
+            download(&http, &url, 8).await,
+            Err(Error::Download { .. })
+        ));
+        assert!(matches!(
+            read_batch(&http, "ftp://example.com/labels.yml").await,
+            Err(Error::Download { .. })
+        ));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::env;
+use crate::error::Error;
+use crate::io::{self, HttpClient};
+use crate::octomate::{Batch, UnknownFields};
+use serde_yaml::Value;
+use std::path::Path;
+
+/// Prefixes of the tokens issued by Github.
+const TOKEN_PREFIXES: [&str; 6] = ["ghp_", "gho_", "ghu_", "ghs_", "ghr_", "github_pat_"];
+
+/// Field names hinting that the value is a credential.
+const SECRET_FIELD_NAMES: [&str; 7] = [
+    "token",
+    "secret",
+    "password",
+    "private-key",
+    "authorization",
+    "cookie",
+    "api-key",
+];
+
+/// A soft warning reported by the linter.
+/// Unlike a parsing error, a warning doesn't prevent the batch from running.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    InlinedSecret {
+        location: String,
+    },
+    Unnamed {
+        location: String,
+    },
+    /// A command that can't be undone, described by `Batch::destructive_commands`.
+    Destructive {
+        command: String,
+    },
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::InlinedSecret { location } => {
+                write!(f, "{}: looks like a secret inlined as a literal", location)
+            }
+            Warning::Unnamed { location } => {
+                write!(f, "{}: has no name, so it can't be referenced", location)
+            }
+            Warning::Destructive { command } => write!(
+                f,
+                "{} can't be undone, it only runs once confirmed or with --allow-destructive",
+                command
+            ),
+        }
+    }
+}
+
+/// Lint a batch from its raw YAML representation.
+pub fn lint(batch: &Value) -> Vec<Warning> {
+    let mut warnings = vec![];
+    lint_unnamed(batch, &mut warnings);
+    lint_secrets(batch, "", &mut warnings);
+    warnings
+}
+
+/// Read the batch file, ensure it's a valid batch and lint it.
+pub async fn lint_file(
+    http: &HttpClient,
+    path: impl AsRef<Path>,
+    unknown_fields: UnknownFields,
+) -> Result<Vec<Warning>, Error> {
+    let bytes = io::read_batch(http, path).await?;
+    let batch = Batch::load(&bytes, unknown_fields)?;
+    let raw: Value = serde_yaml::from_slice(&bytes)?;
+    let mut warnings = lint(&raw);
+    warnings.extend(lint_destructive(&batch));
+    Ok(warnings)
+}
+
+/// Report the commands of the batch that can't be undone.
+pub fn lint_destructive(batch: &Batch) -> Vec<Warning> {
+    batch
+        .destructive_commands()
+        .into_iter()
+        .map(|command| Warning::Destructive { command })
+        .collect()
+}
+
+/// Whether the name of a field or a header hints that its value is a credential.
+pub fn is_secret_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SECRET_FIELD_NAMES
+        .iter()
+        .any(|secret| name.contains(secret))
+}
+
+/// Whether `word` starts like a token issued by Github.
+pub fn looks_like_token(word: &str) -> bool {
+    TOKEN_PREFIXES.iter().any(|prefix| word.starts_with(prefix))
+}
+
+/// Report the jobs and the steps without a name.
+fn lint_unnamed(batch: &Value, warnings: &mut Vec<Warning>) {
+    let jobs = batch.get("jobs").and_then(Value::as_sequence);
+    for (job_index, job) in jobs.into_iter().flatten().enumerate() {
+        let location = format!("jobs[{}]", job_index);
+        let steps = job.get("steps").and_then(Value::as_sequence);
+        if job.get("name").is_none() {
+            warnings.push(Warning::Unnamed {
+                location: location.clone(),
+            });
+        }
+        for (step_index, step) in steps.into_iter().flatten().enumerate() {
+            if step.get("name").is_none() {
+                warnings.push(Warning::Unnamed {
+                    location: format!("{}.steps[{}]", location, step_index),
+                });
+            }
+        }
+    }
+}
+
+fn lint_secrets(value: &Value, location: &str, warnings: &mut Vec<Warning>) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping {
+                let key = match key.as_str() {
+                    Some(key) => key,
+                    None => continue,
+                };
+                let location = if location.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", location, key)
+                };
+                let is_secret_field = is_secret_name(key);
+                match value.as_str() {
+                    // A reference to a variable is the way to keep the secret out of the batch.
+                    Some(literal)
+                        if is_secret_field
+                            && !literal.is_empty()
+                            && !env::is_reference(literal) =>
+                    {
+                        warnings.push(Warning::InlinedSecret { location })
+                    }
+                    _ => lint_secrets(value, &location, warnings),
+                }
+            }
+        }
+        Value::Sequence(sequence) => {
+            for (index, value) in sequence.iter().enumerate() {
+                lint_secrets(value, &format!("{}[{}]", location, index), warnings);
+            }
+        }
+        Value::String(literal) if literal.split_whitespace().any(looks_like_token) => {
+            warnings.push(Warning::InlinedSecret {
+                location: location.to_string(),
+            });
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lint_inlined_secrets() {
+        let batch = r#"
+version: "1.0"
+jobs:
+  - name: "configure"
+    on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - name: "publish"
+        runs:
+          - create-gist:
+              title: "config"
+              content: "api: ghp_0123456789abcdef"
+          - create-label:
+              name: "bug"
+              color: "f29513"
+              description: "Something isn't working"
+"#;
+        let raw: Value = serde_yaml::from_str(batch).unwrap();
+        assert_eq!(
+            lint(&raw),
+            vec![Warning::InlinedSecret {
+                location: "jobs[0].steps[0].runs[0].create-gist.content".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_lint_secret_field_name() {
+        let raw: Value = serde_yaml::from_str("token: hunter2\nname: me").unwrap();
+        assert_eq!(
+            lint(&raw),
+            vec![Warning::InlinedSecret {
+                location: "token".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_lint_secret_reference() {
+        let raw: Value = serde_yaml::from_str(
+            "literal:\n  token: hunter2\nreference:\n  token: \"${GITHUB_TOKEN}\"",
+        )
+        .unwrap();
+        assert_eq!(
+            lint(&raw),
+            vec![Warning::InlinedSecret {
+                location: "literal.token".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_lint_unnamed() {
+        let batch = r#"
+version: "1.0"
+jobs:
+  - name: "labels"
+    on-repositories: []
+    steps:
+      - name: "create"
+        runs: []
+      - runs: []
+  - on-repositories: []
+    steps: []
+"#;
+        let raw: Value = serde_yaml::from_str(batch).unwrap();
+        assert_eq!(
+            lint(&raw),
+            vec![
+                Warning::Unnamed {
+                    location: "jobs[0].steps[1]".to_string()
+                },
+                Warning::Unnamed {
+                    location: "jobs[1]".to_string()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lint_destructive() {
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - name: "publish"
+    on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - set-repository-visibility:
+              visibility: public
+          - set-repository-visibility:
+              visibility: private
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            lint_destructive(&batch),
+            vec![Warning::Destructive {
+                command: "set-repository-visibility in job publish".to_string()
+            }]
+        );
+    }
+}
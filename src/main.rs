@@ -1,47 +1,394 @@
+pub mod cache;
 pub mod command;
+pub mod env;
 pub mod error;
+pub mod graphql;
+pub mod interactive;
 pub mod io;
+pub mod lint;
+pub mod notify;
 pub mod octomate;
 pub mod options;
+pub mod output;
+pub mod palette;
+pub mod plan;
+pub mod pool;
+pub mod progress;
+pub mod provenance;
+pub mod record;
+pub mod rest;
+pub mod retry;
+pub mod rollback;
+pub mod scheduler;
+pub mod summary;
 
 use crate::options::Options;
-use paris::Logger;
+use paris::{warn, Logger};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+const GITHUB_API_URL: &str = "https://api.github.com";
 
 #[tokio::main]
 async fn main() {
     let options = Options::from_cli();
     let mut logger = Logger::new();
+    let http = io::http_client();
+
+    let unknown_fields = match options.lenient {
+        true => octomate::UnknownFields::Ignore,
+        false => octomate::UnknownFields::Deny,
+    };
+    let batch_files = match (&options.batch_file, &options.batch_dir) {
+        (Some(batch_file), _) => vec![PathBuf::from(batch_file)],
+        (None, Some(batch_dir)) => io::list_batch_files(batch_dir)
+            .await
+            .expect("Unable to read the batch directory"),
+        (None, None) => vec![],
+    };
+
+    if options.print_plan_graph {
+        for batch_file in &batch_files {
+            let bytes = io::read_batch(&http, batch_file)
+                .await
+                .expect("Unable to read batch from file");
+            let batch = octomate::Batch::parse(&bytes, unknown_fields).expect("Invalid batch file");
+            print!("{}", plan::to_dot(&batch));
+        }
+        return;
+    }
 
-    let personal_token =
+    if options.dump_effective {
+        for batch_file in &batch_files {
+            let batch = octomate::Batch::from_file(&http, batch_file, unknown_fields)
+                .await
+                .expect("Invalid batch file");
+            print!("{}", batch.to_yaml().expect("Unable to dump the batch"));
+        }
+        return;
+    }
+
+    if options.validate {
+        let mut warnings_count = 0;
+        for batch_file in &batch_files {
+            logger.loading(format!("Validate batch file {:?}", batch_file));
+            let warnings = lint::lint_file(&http, batch_file, unknown_fields)
+                .await
+                .expect("Invalid batch file");
+            logger.done();
+            for warning in &warnings {
+                warn!("{}", warning);
+            }
+            warnings_count += warnings.len();
+        }
+        if options.strict && warnings_count > 0 {
+            logger.error(format!("{} lint warning(s) found", warnings_count));
+            std::process::exit(1);
+        }
+        logger.success("Batch file is valid");
+        return;
+    }
+
+    let mut client_options = octomate::ClientOptions::default();
+    let personal_token = if let Some(dir) = &options.replay {
+        let address = record::start_replayer(dir)
+            .await
+            .expect("Unable to start the replay server");
+        client_options.base_url = Some(format!("http://{}", address));
+        String::from("replay")
+    } else {
         rpassword::prompt_password("Enter your personal access token (scope: repo): ")
-            .expect("You need to enter a valid personal access token");
+            .expect("You need to enter a valid personal access token")
+    };
+
+    if let Some(dir) = &options.record {
+        let address = record::start_recorder(&http, dir, GITHUB_API_URL)
+            .await
+            .expect("Unable to start the record proxy");
+        client_options.base_url = Some(format!("http://{}", address));
+    }
+
+    for (name, value) in &options.headers {
+        let value = match lint::is_secret_name(name) || lint::looks_like_token(value) {
+            true => "***",
+            false => value.as_str(),
+        };
+        logger.info(format!("Extra header {}: {}", name, value));
+    }
+    client_options.headers = options.headers.clone();
+
+    // The prompts of the interactive mode would fight with the bar for the terminal.
+    let progress = (!options.quiet && !options.interactive)
+        .then(progress::Progress::from_tty)
+        .flatten()
+        .map(Arc::new);
 
     logger.loading("Authenticate to github in progress");
-    let octomate = octomate::Octomate::new(personal_token)
+    let mut octomate = octomate::Octomate::with_options(personal_token.clone(), &client_options)
         .await
-        .expect("Unable to init octocrab");
+        .expect("Unable to init octocrab")
+        .with_settings(octomate::Settings {
+            validate_assignees: options.validate_assignees,
+            dry_run: options.dry_run,
+            ignore_missing: options.ignore_missing,
+            stamp_provenance: options.stamp_provenance,
+            provenance_format: options.provenance_format.clone(),
+            interactive: options
+                .interactive
+                .then(|| interactive::Stepper::from_tty().map(Arc::new))
+                .transpose()
+                .expect("Unable to run interactively"),
+            request_timeout: options.request_timeout_secs.map(Duration::from_secs),
+            poll_interval: Some(Duration::from_secs(options.poll_interval_secs)),
+            on_dependency_failure: options.on_dependency_failure,
+            max_parallel_jobs: options.max_parallel_jobs,
+            max_concurrency: options.max_concurrency,
+            fail_fast: options.fail_fast,
+            on_command_complete: None,
+            dump_on_panic: options
+                .dump_results_on_panic
+                .as_ref()
+                .map(|path| summary::PanicDump {
+                    path: path.as_ref().map(PathBuf::from),
+                }),
+            retry: retry::RetryPolicy {
+                base: Duration::from_millis(options.retry_base_ms),
+                max: Duration::from_millis(options.retry_max_ms),
+                jitter: options.retry_jitter,
+                seed: options.retry_seed,
+                ..retry::RetryPolicy::default()
+            },
+            shared: None,
+        });
+    if let Some(progress) = &progress {
+        let progress = progress.clone();
+        octomate = octomate.on_command_complete(move |_, _| progress.advance());
+    }
     logger
         .done()
         .success("Authenticated successfully to github");
 
-    logger.loading(format!("Read batch file {:?}", options.batch_file));
-    octomate
-        .run_batch_from_file(options.batch_file)
-        .await
-        .expect("Unable to run batch from file");
-    logger.done().success("Batch processing terminated");
+    let mut batches = vec![];
+    if let Some(rollback_file) = &options.rollback {
+        let log = rollback::RollbackLog::read(rollback_file)
+            .await
+            .expect("Unable to read the rollback file");
+        for command in &log.not_invertible {
+            warn!("Unable to roll back {}", command);
+        }
+        batches.push(log.to_batch());
+    }
+    for batch_file in &batch_files {
+        logger.loading(format!("Read batch file {:?}", batch_file));
+        let batch = octomate::Batch::from_file(&http, batch_file, unknown_fields)
+            .await
+            .expect("Unable to read batch from file");
+        logger.done();
+        batches.push(batch);
+    }
+
+    let mut totals = summary::Counts::default();
+    let mut rollback_log = rollback::RollbackLog::default();
+    for batch in &batches {
+        if let Err(err) = check_batch(&options, batch, true) {
+            logger.error(err);
+            std::process::exit(1);
+        }
+        // The spinner of paris would be drawn over the bar.
+        match &progress {
+            Some(progress) => {
+                logger.info(format!("Run batch {}", batch.display_name()));
+                progress.start(batch.unit_count());
+            }
+            None => {
+                logger.loading(format!("Run batch {}", batch.display_name()));
+            }
+        }
+        let results = octomate.run_batch(batch).await;
+        if let Some(progress) = &progress {
+            progress.finish();
+        }
+        logger.done().success("Batch processing terminated");
+
+        if let Some(rollback_file) = &options.rollback_file {
+            rollback_log.record(batch, &results);
+            save_rollback_log(&rollback_log, rollback_file, &mut logger).await;
+        }
+
+        print_output(&options, batch, &results);
+
+        let summary = summary::Summary::new(batch, &results);
+        log_summary(&summary, &mut logger);
+        for failure in &summary.failures {
+            logger.error(failure);
+        }
+        totals.merge(&summary.total);
+
+        notify_summary(&http, &options, &summary, &personal_token, &mut logger).await;
+    }
+
+    if options.jobs_from_stdin {
+        let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+        match &progress {
+            Some(progress) => {
+                logger.info("Run the jobs read from stdin");
+                progress.start(0);
+            }
+            None => {
+                logger.loading("Run the jobs read from stdin");
+            }
+        }
+        // Stdin is the stream of the jobs, so it can't confirm the destructive ones.
+        let summary = octomate
+            .run_jobs_from_reader(
+                "stdin",
+                stdin,
+                unknown_fields,
+                |batch| {
+                    check_batch(&options, batch, false)?;
+                    if let Some(progress) = &progress {
+                        progress.extend(batch.unit_count());
+                    }
+                    Ok(())
+                },
+                |batch, results| {
+                    if options.rollback_file.is_some() {
+                        rollback_log.record(batch, results);
+                    }
+                    print_output(&options, batch, results);
+                },
+            )
+            .await
+            .expect("Unable to read the jobs from stdin");
+        if let Some(progress) = &progress {
+            progress.finish();
+        }
+        logger.done().success("Batch processing terminated");
+        if let Some(rollback_file) = &options.rollback_file {
+            save_rollback_log(&rollback_log, rollback_file, &mut logger).await;
+        }
+        log_summary(&summary, &mut logger);
+        for failure in &summary.failures {
+            logger.error(failure);
+        }
+        totals.merge(&summary.total);
+        notify_summary(&http, &options, &summary, &personal_token, &mut logger).await;
+    }
+
+    let threshold = options.failure_threshold();
+    if options.max_failures.is_some() || options.max_failure_rate.is_some() {
+        logger.info(format!(
+            "{} of {} command(s) failed, tolerating {}",
+            totals.failed,
+            totals.total(),
+            threshold
+        ));
+    }
+    if threshold.is_exceeded(&totals) {
+        std::process::exit(1);
+    }
+}
+
+/// Check a batch against the options before running it: the count of its repositories,
+/// whether it targets some, and the confirmation of its destructive commands.
+/// Without `prompt`, the destructive commands are refused unless they're allowed.
+fn check_batch(options: &Options, batch: &octomate::Batch, prompt: bool) -> Result<(), String> {
+    if let Some(expected) = options.confirm_count {
+        batch
+            .confirm_repository_count(expected)
+            .map_err(|err| err.to_string())?;
+    }
+    if options.require_repos {
+        batch
+            .require_repositories()
+            .map_err(|err| format!("Batch {} aborted: {}", batch.display_name(), err))?;
+    }
+    for job in batch.jobs_without_repositories() {
+        warn!(
+            "Job {} targets no repository",
+            job.name.as_deref().unwrap_or("UNAMED")
+        );
+    }
+    let destructive_commands = batch.destructive_commands();
+    if destructive_commands.is_empty() || options.allow_destructive || options.dry_run {
+        return Ok(());
+    }
+    if !prompt {
+        return Err(format!(
+            "Batch {} aborted: {} can't be undone without --allow-destructive",
+            batch.display_name(),
+            destructive_commands.join(", ")
+        ));
+    }
+    for command in &destructive_commands {
+        warn!("{} can't be undone", command);
+    }
+    let answer = rprompt::prompt_reply_stdout("Type yes to run them anyway: ").unwrap_or_default();
+    match answer.trim() {
+        "yes" => Ok(()),
+        _ => Err(format!("Batch {} aborted", batch.display_name())),
+    }
+}
+
+async fn save_rollback_log(
+    rollback_log: &rollback::RollbackLog,
+    rollback_file: &str,
+    logger: &mut Logger<'_>,
+) {
+    match rollback_log.write(rollback_file).await {
+        Ok(()) => logger.success(format!("Rollback log saved to {}", rollback_file)),
+        Err(err) => logger.error(format!("Unable to save the rollback log: {}", err)),
+    };
+}
+
+/// Print the lines of the output template for the results of the batch, if there's one.
+fn print_output(options: &Options, batch: &octomate::Batch, results: &octomate::BatchResult) {
+    if let Some(template) = &options.output_template {
+        for line in template.lines(batch, results) {
+            println!("{}", line);
+        }
+    }
+}
+
+/// Log the counts of each kind of command, then the grand total.
+fn log_summary(summary: &summary::Summary, logger: &mut Logger<'_>) {
+    for (command, counts) in &summary.by_command {
+        logger.info(format!("{}: {}", command, counts));
+    }
+    if !summary.post.is_empty() {
+        logger.info(format!("post steps: {}", summary.post));
+    }
+    logger.info(summary);
+}
+
+/// POST the summary to the notification URL, if there's one and the summary is worth it.
+async fn notify_summary(
+    http: &io::HttpClient,
+    options: &Options,
+    summary: &summary::Summary,
+    personal_token: &str,
+    logger: &mut Logger<'_>,
+) {
+    if let Some(url) = &options.notify_url {
+        if options.notify_on.should_notify(summary) {
+            match notify::notify(http, url, summary, &[personal_token]).await {
+                Ok(()) => logger.success("Summary sent to the notification URL"),
+                Err(err) => logger.error(format!("Unable to send the summary: {}", err)),
+            };
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use mockito::mock;
-    use octocrab::models::Label;
 
     #[tokio::test]
     async fn test_create_label() {
         let url = &mockito::server_url();
-        let octocrab = octocrab::Octocrab::builder()
+        let _octocrab = octocrab::Octocrab::builder()
             .personal_token("test".to_owned())
             .base_url(url)
             .unwrap()
@@ -90,20 +437,34 @@ jobs:
             octomate::Batch {
                 version: "1.0".to_owned(),
                 name: Some("Test".to_owned()),
+                stamp_provenance: None,
+                env: None,
+                repository_groups: None,
                 jobs: vec![octomate::Job {
                     name: Some("Perform some basics things for some repos".to_owned()),
+                    needs: None,
+                    env: None,
                     on_repositories: vec![octomate::Repository {
                         owner: "me".to_owned(),
                         name: "repo1".to_owned(),
                     }],
+                    on_repository_groups: None,
+                    exclude_repositories: None,
+                    pre: None,
                     steps: vec![octomate::Step {
+                        env: None,
                         name: Some("Hello world!".to_owned()),
-                        runs: vec![command::Command::CreateLabel(command::CreateLabelOptions {
-                            name: "bug".to_owned(),
-                            color: "f29513".to_owned(),
-                            description: "Something isn't working".to_owned(),
-                        })]
-                    }]
+                        retries: None,
+                        runs: vec![command::Command::CreateLabel(
+                            command::CreateLabelOptions {
+                                name: "bug".to_owned(),
+                                color: "f29513".to_owned(),
+                                description: "Something isn't working".to_owned(),
+                            }
+                            .into()
+                        )]
+                    }],
+                    post: None,
                 }]
             }
         );
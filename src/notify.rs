@@ -0,0 +1,113 @@
+use crate::error::Error;
+use crate::io::HttpClient;
+use crate::lint;
+use crate::summary::Summary;
+use clap::ValueEnum;
+use hyper::{Body, Request};
+
+/// When to send the notification.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum NotifyOn {
+    Failure,
+    Always,
+}
+
+impl NotifyOn {
+    pub fn should_notify(&self, summary: &Summary) -> bool {
+        match self {
+            Self::Failure => summary.has_failures(),
+            Self::Always => true,
+        }
+    }
+}
+
+/// POST the summary of a batch as JSON to `url`.
+/// The `secrets` and anything looking like a Github token are masked from the payload.
+pub async fn notify(
+    http: &HttpClient,
+    url: &str,
+    summary: &Summary,
+    secrets: &[&str],
+) -> Result<(), Error> {
+    let mut payload = serde_json::to_value(summary)?;
+    payload["text"] = serde_json::Value::String(summary.to_string());
+    let payload = mask(&payload.to_string(), secrets);
+
+    let request = Request::post(url)
+        .header("content-type", "application/json")
+        .body(Body::from(payload))?;
+    let response = http.request(request).await?;
+    if !response.status().is_success() {
+        return Err(Error::Notification(response.status().as_u16()));
+    }
+    Ok(())
+}
+
+fn mask(payload: &str, secrets: &[&str]) -> String {
+    let mut masked = payload.to_string();
+    for secret in secrets.iter().filter(|secret| !secret.is_empty()) {
+        masked = masked.replace(secret, "***");
+    }
+    masked
+        .split_inclusive(|c: char| !c.is_alphanumeric() && c != '_')
+        .map(|word| {
+            let token = word.trim_end_matches(|c: char| !c.is_alphanumeric() && c != '_');
+            if lint::looks_like_token(token) {
+                word.replacen(token, "***", 1)
+            } else {
+                word.to_string()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::summary::Counts;
+    use mockito::{mock, Matcher};
+
+    #[tokio::test]
+    async fn test_notify_masks_secrets() {
+        let summary = Summary {
+            batch: "Test".to_string(),
+            total: Counts {
+                succeeded: 1,
+                failed: 1,
+                ..Counts::default()
+            },
+            failures: vec!["Bad credentials for hunter2 and ghp_0123456789".to_string()],
+            ..Summary::default()
+        };
+        assert!(NotifyOn::Failure.should_notify(&summary));
+
+        let m = mock("POST", "/hook")
+            .match_header("content-type", "application/json")
+            .match_body(Matcher::Json(serde_json::json!({
+                "batch": "Test",
+                "succeeded": 1,
+                "failed": 1,
+                "skipped": 0,
+                "dry_run": 0,
+                "by_command": {},
+                "failures": ["Bad credentials for *** and ***"],
+                "text": "Batch Test: 1 succeeded, 1 failed",
+            })))
+            .with_status(200)
+            .expect(1)
+            .create();
+
+        let url = format!("{}/hook", mockito::server_url());
+        notify(&crate::io::http_client(), &url, &summary, &["hunter2"])
+            .await
+            .unwrap();
+        m.assert();
+    }
+
+    #[test]
+    fn test_notify_on() {
+        let summary = Summary::default();
+        assert!(!NotifyOn::Failure.should_notify(&summary));
+        assert!(NotifyOn::Always.should_notify(&summary));
+    }
+}
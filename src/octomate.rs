@@ -1,37 +1,184 @@
-use crate::command;
+use crate::cache::RepositoryCache;
+use crate::command::{self, CommandInfo};
+use crate::env::{self, Env};
 use crate::error::Error;
-use crate::io;
+use crate::interactive::Stepper;
+use crate::io::{self, HttpClient};
+use crate::pool::ClientPool;
+use crate::retry::RetryPolicy;
+use crate::scheduler::{Scheduler, SharedRun};
+use crate::summary::{PanicDump, Summary};
+use clap::ValueEnum;
+use futures::stream::{FuturesUnordered, StreamExt};
+use hyper::header::{HeaderName, HeaderValue};
 use octocrab::Octocrab;
-use paris::info;
-use serde::Deserialize;
+use paris::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+/// Settings of a batch run, shared by every command.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    /// Check the assignees of the issues before creating them.
+    pub validate_assignees: bool,
+    /// Describe the commands instead of running them.
+    pub dry_run: bool,
+    /// Skip the deletions failing because the target is already missing.
+    pub ignore_missing: bool,
+    /// Append a provenance footer to the bodies of the issues and gists created.
+    pub stamp_provenance: bool,
+    /// Format of the provenance footer, defaults to `provenance::DEFAULT_FORMAT`.
+    pub provenance_format: Option<String>,
+    /// Maximum duration of each request sent by the commands.
+    /// octocrab doesn't expose the timeouts of its HTTP client,
+    /// so `rest` enforces it around each request instead.
+    pub request_timeout: Option<Duration>,
+    /// How the requests failing with a rate limit or a server error are retried.
+    pub retry: RetryPolicy,
+    /// Ask before each step whether to run it.
+    /// Jobs and steps then run one after the other.
+    pub interactive: Option<Arc<Stepper>>,
+    /// What to do with a job when a job it needs failed.
+    pub on_dependency_failure: DependencyFailure,
+    /// Delay between two checks of what a command waits for, like a workflow run,
+    /// defaults to `POLL_INTERVAL`.
+    pub poll_interval: Option<Duration>,
+    /// Maximum number of jobs running at once, unlimited by default.
+    /// The commands of a running job still run concurrently.
+    pub max_parallel_jobs: Option<usize>,
+    /// Maximum number of commands running at once, counting each repository they run on,
+    /// whatever their job. Unlimited by default.
+    pub max_concurrency: Option<usize>,
+    /// Skip every command not started yet once one failed.
+    pub fail_fast: bool,
+    /// Called after each command completed on each repository.
+    pub on_command_complete: Option<CommandHook>,
+    /// Dump the summary of the results so far when a command panics.
+    pub dump_on_panic: Option<PanicDump>,
+    /// Schedule the batch with the others of the shared run, like the streamed jobs.
+    pub shared: Option<SharedRun>,
+}
+
+/// Delay between two checks of what a command waits for, unless the settings tell otherwise.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+impl Settings {
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval.unwrap_or(POLL_INTERVAL)
+    }
+}
+
+/// A callback observing the commands as they complete, for metrics or audit logs.
+#[derive(Clone)]
+pub struct CommandHook(Arc<HookFn>);
+
+type HookFn = dyn Fn(&CommandInfo, &command::Outcome) + Send + Sync;
+
+impl CommandHook {
+    pub fn new(hook: impl Fn(&CommandInfo, &command::Outcome) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(hook))
+    }
+
+    pub fn call(&self, info: &CommandInfo, outcome: &command::Outcome) {
+        (self.0)(info, outcome)
+    }
+}
+
+impl std::fmt::Debug for CommandHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CommandHook")
+    }
+}
 
 #[derive(Debug)]
 pub struct Context<'a> {
     pub batch: &'a Batch,
+    pub settings: &'a Settings,
+    pub cache: &'a RepositoryCache,
     pub job: Option<&'a Job>,
     pub step: Option<&'a Step>,
+    /// The command running.
+    pub command: Option<&'a command::Command>,
+    /// The repository of the job the commands run on, rather than every one of them:
+    /// the scheduler runs each command on each repository as a unit of its own.
+    pub repository: Option<&'a Repository>,
 }
 
 impl<'a> Context<'a> {
-    pub fn new(batch: &'a Batch, job: Option<&'a Job>, step: Option<&'a Step>) -> Self {
-        Self { batch, job, step }
+    pub fn new(
+        batch: &'a Batch,
+        settings: &'a Settings,
+        cache: &'a RepositoryCache,
+        job: Option<&'a Job>,
+        step: Option<&'a Step>,
+    ) -> Self {
+        Self {
+            batch,
+            settings,
+            cache,
+            job,
+            step,
+            command: None,
+            repository: None,
+        }
     }
 
     pub fn update_from_job(&self, job: &'a Job) -> Self {
         Self {
             batch: self.batch,
+            settings: self.settings,
+            cache: self.cache,
             job: Some(job),
             step: self.step,
+            command: self.command,
+            repository: self.repository,
         }
     }
 
     pub fn update_from_step(&self, step: &'a Step) -> Self {
         Self {
             batch: self.batch,
+            settings: self.settings,
+            cache: self.cache,
             job: self.job,
             step: Some(step),
+            command: self.command,
+            repository: self.repository,
+        }
+    }
+
+    pub fn update_from_command<'b>(&self, command: &'b command::Command) -> Context<'b>
+    where
+        'a: 'b,
+    {
+        Context {
+            batch: self.batch,
+            settings: self.settings,
+            cache: self.cache,
+            job: self.job,
+            step: self.step,
+            command: Some(command),
+            repository: self.repository,
+        }
+    }
+
+    pub fn update_from_repository(&self, repository: &'a Repository) -> Self {
+        Self {
+            repository: Some(repository),
+            ..self.into()
+        }
+    }
+
+    /// The repositories the commands run on, `None` outside of a job.
+    pub fn repositories(&self) -> Option<&'a [Repository]> {
+        match (self.job, self.repository) {
+            (None, _) => None,
+            (Some(_), Some(repository)) => Some(std::slice::from_ref(repository)),
+            (Some(job), None) => Some(&job.on_repositories),
         }
     }
 }
@@ -40,128 +187,1269 @@ impl<'a> From<&Context<'a>> for Context<'a> {
     fn from(ctx: &Context<'a>) -> Self {
         Self {
             batch: ctx.batch,
+            settings: ctx.settings,
+            cache: ctx.cache,
             job: ctx.job,
             step: ctx.step,
+            command: ctx.command,
+            repository: ctx.repository,
         }
     }
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
 pub struct Batch {
     pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    /// Stamp what the batch creates with a provenance footer, defaults to `--stamp-provenance`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stamp_provenance: Option<bool>,
+    /// Variables of the `${NAME}` references of the commands, overridden by the jobs and steps.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<Env>,
+    /// Lists of repositories shared by the jobs, by name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repository_groups: Option<HashMap<String, Vec<Repository>>>,
     pub jobs: Vec<Job>,
 }
 
-type BatchResult<Output, Err> = Vec<Vec<StepResult<Output, Err>>>;
+pub type BatchResult = Vec<Vec<StepResult>>;
 
 impl Batch {
-    pub async fn run(&self, octocrab: &Octocrab) -> BatchResult<command::Response, Error> {
+    /// Load a batch from a local path or an HTTP(S) URL.
+    pub async fn from_file(
+        http: &HttpClient,
+        filepath: impl AsRef<Path>,
+        unknown_fields: UnknownFields,
+    ) -> Result<Self, Error> {
+        let bytes = io::read_batch(http, filepath).await?;
+        Batch::load(&bytes, unknown_fields)
+    }
+
+    /// Parse and validate a batch.
+    pub fn load(batch_file: &[u8], unknown_fields: UnknownFields) -> Result<Self, Error> {
+        let batch = Batch::parse(batch_file, unknown_fields)?;
+        batch.validate()?;
+        Ok(batch)
+    }
+
+    /// Parse a batch and expand its variables without validating it, to inspect an invalid batch.
+    pub fn parse(batch_file: &[u8], unknown_fields: UnknownFields) -> Result<Self, Error> {
+        let raw: serde_yaml::Value = serde_yaml::from_slice(batch_file)?;
+        let mut batch: Batch = serde_yaml::from_value(raw.clone())?;
+        if unknown_fields == UnknownFields::Deny {
+            deny_unknown_fields(&raw, &serde_yaml::to_value(&batch)?, "batch")?;
+        }
+        batch.resolve_repository_groups()?;
+        env::resolve(&mut batch)?;
+        Ok(batch)
+    }
+
+    /// Expand the repository groups of the jobs into their repositories, without duplicates
+    /// and without the excluded ones.
+    fn resolve_repository_groups(&mut self) -> Result<(), Error> {
+        let groups = self.repository_groups.clone().unwrap_or_default();
+        for job in &mut self.jobs {
+            let mut repositories = std::mem::take(&mut job.on_repositories);
+            for name in job.on_repository_groups.take().into_iter().flatten() {
+                let group = groups.get(&name).ok_or_else(|| {
+                    Error::Validation(format!(
+                        "job {} runs on the unknown repository group {}",
+                        job.name.as_deref().unwrap_or("UNAMED"),
+                        name
+                    ))
+                })?;
+                repositories.extend(group.iter().cloned());
+            }
+            let excluded: HashSet<Repository> = job
+                .exclude_repositories
+                .take()
+                .into_iter()
+                .flatten()
+                .collect();
+            let mut seen = HashSet::new();
+            job.on_repositories = repositories
+                .into_iter()
+                .filter(|repository| !excluded.contains(repository))
+                .filter(|repository| seen.insert(repository.clone()))
+                .collect();
+        }
+        Ok(())
+    }
+
+    /// The batch as it's run, serialized as YAML.
+    pub fn to_yaml(&self) -> Result<String, Error> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+
+    /// Check the constraints that can't be expressed by the batch format itself.
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut names = HashSet::new();
+        for name in self.jobs.iter().filter_map(|job| job.name.as_ref()) {
+            if !names.insert(name) {
+                return Err(Error::DuplicateJobName { name: name.clone() });
+            }
+        }
+        for job in &self.jobs {
+            if let Some(need) = job.needs().find(|need| !names.contains(need)) {
+                return Err(Error::Validation(format!(
+                    "job {} needs the unknown job {}",
+                    job.name.as_deref().unwrap_or("UNAMED"),
+                    need
+                )));
+            }
+        }
+        self.validate_needs_are_acyclic()?;
+        self.jobs
+            .iter()
+            .flat_map(Job::all_steps)
+            .flat_map(|step| &step.runs)
+            .try_for_each(|command| command.validate())
+    }
+
+    /// Fail if some jobs need each other, as none of them could ever start.
+    fn validate_needs_are_acyclic(&self) -> Result<(), Error> {
+        let (_, waiting) = self.topological_layers();
+        if waiting.is_empty() {
+            return Ok(());
+        }
+        let names: Vec<&str> = waiting
+            .iter()
+            .map(|job| job.name.as_deref().unwrap_or("UNAMED"))
+            .collect();
+        Err(Error::Validation(format!(
+            "circular needs between the jobs {}",
+            names.join(", ")
+        )))
+    }
+
+    /// Group the jobs by the order they can start in: each layer only needs the jobs
+    /// of the previous layers. The jobs left apart are in a cycle of needs, or need one of them.
+    pub fn topological_layers(&self) -> (Vec<Vec<&Job>>, Vec<&Job>) {
+        let mut layers = vec![];
+        let mut complete: HashSet<&str> = HashSet::new();
+        let mut remaining: Vec<&Job> = self.jobs.iter().collect();
+        while !remaining.is_empty() {
+            let (ready, waiting): (Vec<&Job>, Vec<&Job>) = remaining
+                .into_iter()
+                .partition(|job| job.needs().all(|need| complete.contains(need.as_str())));
+            if ready.is_empty() {
+                return (layers, waiting);
+            }
+            complete.extend(ready.iter().filter_map(|job| job.name.as_deref()));
+            layers.push(ready);
+            remaining = waiting;
+        }
+        (layers, vec![])
+    }
+
+    /// Number of distinct repositories targeted by the jobs of the batch.
+    pub fn repository_count(&self) -> usize {
+        self.jobs
+            .iter()
+            .flat_map(|job| &job.on_repositories)
+            .map(|repository| (&repository.owner, &repository.name))
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Number of outcomes of the batch: one per command and repository of its job,
+    /// or one per command for the commands not running per repository.
+    pub fn unit_count(&self) -> usize {
+        self.jobs
+            .iter()
+            .flat_map(|job| job.all_steps().map(move |step| (job, step)))
+            .flat_map(|(job, step)| step.runs.iter().map(move |command| (job, command)))
+            .map(|(job, command)| match command.is_per_repository() {
+                true => job.on_repositories.len() * command.units_per_repository(),
+                false => 1,
+            })
+            .sum()
+    }
+
+    /// Describe the destructive commands of the batch, to confirm before running it.
+    pub fn destructive_commands(&self) -> Vec<String> {
+        self.jobs
+            .iter()
+            .flat_map(|job| {
+                job.all_steps()
+                    .flat_map(|step| step.runs.iter())
+                    .filter(|command| command.is_destructive())
+                    .map(move |command| {
+                        format!(
+                            "{} in job {}",
+                            command.name(),
+                            job.name.as_deref().unwrap_or("UNAMED")
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// Ensure the batch targets exactly `expected` repositories.
+    pub fn confirm_repository_count(&self, expected: usize) -> Result<(), Error> {
+        let actual = self.repository_count();
+        if actual == expected {
+            Ok(())
+        } else {
+            Err(Error::UnexpectedRepositoryCount { expected, actual })
+        }
+    }
+
+    /// Fail if the jobs target no repository at all, like when a typo in the exclusions
+    /// leaves none of them, rather than run a batch doing nothing.
+    pub fn require_repositories(&self) -> Result<(), Error> {
+        match self.repository_count() {
+            0 => Err(Error::NoRepositoriesTargeted),
+            _ => Ok(()),
+        }
+    }
+
+    /// The jobs running commands per repository on no repository, which do nothing.
+    pub fn jobs_without_repositories(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter().filter(|job| {
+            job.on_repositories.is_empty()
+                && job
+                    .all_steps()
+                    .flat_map(|step| &step.runs)
+                    .any(|command| command.is_per_repository())
+        })
+    }
+
+    pub fn display_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| "UNAMED".to_string())
+    }
+
+    /// Run the jobs concurrently, each one once the jobs it needs are complete,
+    /// and the steps of each job one after the other, see `Scheduler`.
+    /// Jobs run one after the other if they're run interactively.
+    /// Results come in the order of `jobs`.
+    pub async fn run(&self, octocrab: &Octocrab, settings: &Settings) -> BatchResult {
         println!();
         info!(
             "Running batch: {} with version specs: {}",
-            &self.name.clone().unwrap_or("UNAMED".to_string()),
+            &self.display_name(),
             &self.version,
         );
-        let jobs = &self.jobs;
-        let jobs_iter = jobs
-            .iter()
-            .map(|job| async move { job.run(octocrab, &Context::new(self, None, None)).await });
-        futures::future::join_all(jobs_iter).await
+        let cache = RepositoryCache::default();
+        let ctx = Context::new(self, settings, &cache, None, None);
+        Scheduler::new(&ctx).run(octocrab).await
     }
 }
 
+/// What to do with a job when a job it needs failed.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum DependencyFailure {
+    /// Skip the job, and the jobs needing it.
+    #[default]
+    Skip,
+    /// Run the job anyway.
+    Run,
+    /// Skip every job not started yet.
+    Abort,
+}
+
 impl TryFrom<&[u8]> for Batch {
     type Error = Error;
 
     fn try_from(batch_file: &[u8]) -> Result<Self, Self::Error> {
-        let batch = serde_yaml::from_slice(&batch_file)?;
-        Ok(batch)
+        Batch::load(batch_file, UnknownFields::Deny)
     }
 }
 
-#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+/// What to do with the fields of a batch file that aren't part of the batch format,
+/// like a mistyped field name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownFields {
+    #[default]
+    Deny,
+    /// Ignore them, to run batch files written for a newer version of octomate.
+    Ignore,
+}
+
+/// Fail on the first field of `raw` missing from `parsed`, the same batch serialized back
+/// once parsed: serde drops the fields it doesn't know while parsing.
+/// Whatever the structure of the batch, it mirrors `#[serde(deny_unknown_fields)]`,
+/// which can't be turned off at runtime.
+fn deny_unknown_fields(
+    raw: &serde_yaml::Value,
+    parsed: &serde_yaml::Value,
+    location: &str,
+) -> Result<(), Error> {
+    use serde_yaml::Value;
+
+    let name = |key: &Value| match key {
+        Value::String(key) => key.clone(),
+        key => serde_yaml::to_string(key)
+            .map(|key| key.trim_start_matches("---").trim().to_string())
+            .unwrap_or_default(),
+    };
+    match (raw, parsed) {
+        (Value::Mapping(raw), Value::Mapping(parsed)) => {
+            for (key, value) in raw {
+                // An explicit null is parsed as a missing field, which isn't serialized back.
+                if value.is_null() {
+                    continue;
+                }
+                let key = name(key);
+                match parsed
+                    .iter()
+                    .find(|(parsed_key, _)| name(parsed_key) == key)
+                {
+                    None => {
+                        return Err(Error::UnknownField {
+                            field: key,
+                            location: location.to_string(),
+                        })
+                    }
+                    Some((_, parsed)) => {
+                        deny_unknown_fields(value, parsed, &format!("{}.{}", location, key))?
+                    }
+                }
+            }
+            Ok(())
+        }
+        (Value::Sequence(raw), Value::Sequence(parsed)) => {
+            for (index, (raw, parsed)) in raw.iter().zip(parsed).enumerate() {
+                deny_unknown_fields(raw, parsed, &format!("{}[{}]", location, index))?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub struct Job {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    /// Names of the jobs to complete before this one starts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub needs: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<Env>,
+    #[serde(default)]
     pub on_repositories: Vec<Repository>,
+    /// Names of the repository groups of the batch to add to `on_repositories`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_repository_groups: Option<Vec<String>>,
+    /// Repositories to remove from `on_repositories` and the groups.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude_repositories: Option<Vec<Repository>>,
+    /// Steps run before `steps`, like setting up what they need.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre: Option<Vec<Step>>,
     pub steps: Vec<Step>,
+    /// Steps run after the others once the job started, even when they failed
+    /// or the batch was cancelled meanwhile, like cleaning up what `pre` set up.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post: Option<Vec<Step>>,
 }
 
 impl Job {
-    pub async fn run(
-        &self,
-        octocrab: &Octocrab,
-        ctx: &Context<'_>,
-    ) -> Vec<StepResult<command::Response, Error>> {
-        info!(
-            "job: {}",
-            &self.name.clone().unwrap_or("UNAMED".to_string())
-        );
-        let steps = &self.steps;
-        let steps_iter = steps
-            .iter()
-            .map(|step| async move { step.run(octocrab, &ctx.update_from_job(self)).await });
-        futures::future::join_all(steps_iter).await
+    pub fn needs(&self) -> impl Iterator<Item = &String> {
+        self.needs.iter().flatten()
+    }
+
+    /// The steps of the job in the order they run, `pre`, `steps` then `post`, with their phase.
+    /// The results of a job come in this order.
+    pub fn phases(&self) -> impl Iterator<Item = (Phase, &Step)> {
+        let pre = self.pre.iter().flatten().map(|step| (Phase::Pre, step));
+        let main = self.steps.iter().map(|step| (Phase::Main, step));
+        let post = self.post.iter().flatten().map(|step| (Phase::Post, step));
+        pre.chain(main).chain(post)
+    }
+
+    pub fn all_steps(&self) -> impl Iterator<Item = &Step> {
+        self.phases().map(|(_, step)| step)
+    }
+
+    pub fn all_steps_mut(&mut self) -> impl Iterator<Item = &mut Step> {
+        self.pre
+            .iter_mut()
+            .flatten()
+            .chain(&mut self.steps)
+            .chain(self.post.iter_mut().flatten())
     }
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+/// Part of a job a step belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Pre,
+    Main,
+    /// Run even when the steps before failed.
+    Post,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Repository {
     pub owner: String,
     pub name: String,
 }
 
-#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct Step {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<Env>,
+    /// Number of retries of the failed requests of the commands, overriding the retry policy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
     pub runs: Vec<command::Command>,
 }
 
-type StepResult<Output, Err> = Vec<Vec<Result<Output, Err>>>;
+pub type StepResult = Vec<Vec<command::Outcome>>;
 
-impl Step {
-    pub async fn run(
-        &self,
-        octocrab: &Octocrab,
-        ctx: &Context<'_>,
-    ) -> StepResult<command::Response, Error> {
-        info!(
-            "step: {}",
-            &self.name.clone().unwrap_or("UNAMED".to_string())
-        );
-        let runs = &self.runs;
-        let runs_iter = runs
-            .iter()
-            .map(|command| async move { command.run(octocrab, &ctx.update_from_step(self)).await });
-        futures::future::join_all(runs_iter).await
-    }
+/// Options used to build the Github API client.
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    /// Base url of the Github API, defaults to `https://api.github.com`.
+    pub base_url: Option<String>,
+    /// Headers added to every request, as `(name, value)`.
+    pub headers: Vec<(String, String)>,
+}
+
+/// Parse a header written as `Name: Value`.
+pub fn parse_header(header: &str) -> Result<(String, String), Error> {
+    let invalid_header = || Error::InvalidHeader(header.to_string());
+    let (name, value) = header.split_once(':').ok_or_else(invalid_header)?;
+    let (name, value) = (name.trim(), value.trim());
+    HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid_header())?;
+    HeaderValue::from_str(value).map_err(|_| invalid_header())?;
+    Ok((name.to_string(), value.to_string()))
 }
 
 pub struct Octomate {
+    pool: ClientPool,
+    /// The client of the options, used by the batches.
     octocrab: Arc<Octocrab>,
+    settings: Settings,
 }
 
 impl Octomate {
     pub async fn new(personal_token: impl Into<String>) -> Result<Self, Error> {
-        let octocrab = octocrab::Octocrab::builder()
-            .personal_token(personal_token.into())
-            .build()?;
+        Self::with_options(personal_token, &ClientOptions::default()).await
+    }
+
+    pub async fn with_options(
+        personal_token: impl Into<String>,
+        options: &ClientOptions,
+    ) -> Result<Self, Error> {
+        let pool = ClientPool::new(options);
+        let octocrab = pool.client(options.base_url.as_deref(), &personal_token.into())?;
         Ok(Self {
-            octocrab: Arc::new(octocrab),
+            pool,
+            octocrab,
+            settings: Settings::default(),
         })
     }
 
-    pub async fn run_batch(&self, batch: &Batch) -> BatchResult<command::Response, Error> {
-        batch.run(&self.octocrab).await
+    /// The client for another host or credentials, shared with the other users of the same ones.
+    pub fn client(&self, host: Option<&str>, personal_token: &str) -> Result<Arc<Octocrab>, Error> {
+        self.pool.client(host, personal_token)
+    }
+
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Call `hook` after each command completed on each repository, with its outcome.
+    /// Set it after `with_settings`, which replaces it.
+    pub fn on_command_complete(
+        mut self,
+        hook: impl Fn(&CommandInfo, &command::Outcome) + Send + Sync + 'static,
+    ) -> Self {
+        self.settings.on_command_complete = Some(CommandHook::new(hook));
+        self
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub async fn run_batch(&self, batch: &Batch) -> BatchResult {
+        batch.run(&self.octocrab, &self.settings).await
     }
 
     pub async fn run_batch_from_file(
         &self,
+        http: &HttpClient,
         filepath: impl AsRef<Path>,
-    ) -> Result<BatchResult<command::Response, Error>, Error> {
-        let bytes = io::read_file(filepath).await?;
-        let batch = Batch::try_from(bytes.as_slice())?;
+        unknown_fields: UnknownFields,
+    ) -> Result<BatchResult, Error> {
+        let batch = Batch::from_file(http, filepath, unknown_fields).await?;
         Ok(self.run_batch(&batch).await)
     }
+
+    /// Run the jobs read from `reader` as newline-delimited JSON, each one as soon as it's read,
+    /// at most `max_parallel_jobs` at once, or `max_concurrency` if it's the only one set.
+    /// Only the counts of the outcomes are kept, so the memory stays flat however many jobs
+    /// come in, as long as one of them is set.
+    /// Each job runs on its own, so streamed jobs can't need each other, but they share
+    /// `max_concurrency` and `fail_fast`: no more job is read once one failed.
+    /// `admit` is called with the batch of each job before it runs, and the job is counted
+    /// as failed with the error it returns instead, then `on_complete` once it's complete.
+    pub async fn run_jobs_from_reader(
+        &self,
+        name: &str,
+        reader: impl AsyncBufRead + Unpin,
+        unknown_fields: UnknownFields,
+        mut admit: impl FnMut(&Batch) -> Result<(), String>,
+        mut on_complete: impl FnMut(&Batch, &BatchResult),
+    ) -> Result<Summary, Error> {
+        let shared = SharedRun::new(self.settings.max_concurrency);
+        let settings = &Settings {
+            shared: Some(shared.clone()),
+            ..self.settings.clone()
+        };
+        let max_running = self
+            .settings
+            .max_parallel_jobs
+            .or(self.settings.max_concurrency)
+            .unwrap_or(usize::MAX)
+            .max(1);
+        let mut summary = Summary {
+            batch: name.to_string(),
+            ..Summary::default()
+        };
+        let mut lines = reader.lines();
+        let mut running = FuturesUnordered::new();
+        let mut line_number = 0;
+        loop {
+            while running.len() >= max_running {
+                if let Some((batch, results)) = running.next().await {
+                    on_complete(&batch, &results);
+                    summary.add(&batch, &results);
+                }
+            }
+            if let Some(reason) = shared.cancelled() {
+                warn!("No more job read from {}, {}", name, reason);
+                break;
+            }
+            let line = match lines.next_line().await? {
+                None => break,
+                Some(line) => line,
+            };
+            line_number += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let batch = serde_json::from_str::<serde_json::Value>(&line)
+                .map_err(Error::from)
+                .and_then(|job| {
+                    let batch =
+                        serde_json::json!({ "version": "1.0", "name": name, "jobs": [job] });
+                    Batch::load(&serde_json::to_vec(&batch)?, unknown_fields)
+                })
+                .map_err(|err| err.to_string())
+                .and_then(|batch| admit(&batch).map(|()| batch));
+            match batch {
+                Ok(batch) => running.push(async move {
+                    let results = batch.run(&self.octocrab, settings).await;
+                    (batch, results)
+                }),
+                Err(err) => summary.add_failure(format!("line {}: {}", line_number, err)),
+            }
+        }
+        while let Some((batch, results)) = running.next().await {
+            on_complete(&batch, &results);
+            summary.add(&batch, &results);
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::Outcome;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Response, Server};
+    use mockito::mock;
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const LABEL: &str = r#"{
+        "id": 208045946,
+        "node_id": "MDU6TGFiZWwyMDgwNDU5NDY=",
+        "url": "https://api.github.com/repos/me/repo2/labels/bug",
+        "name": "bug",
+        "description": "Something isn't working",
+        "color": "f29513",
+        "default": true
+    }"#;
+
+    const BATCH: &str = r#"
+version: "1.0"
+jobs:
+  - name: "third"
+    needs: ["second"]
+    on-repositories:
+      - owner: me
+        name: repo3
+    steps:
+      - name: "label"
+        runs:
+          - create-label:
+              name: "bug"
+              color: "f29513"
+              description: "Something isn't working"
+  - name: "second"
+    needs: ["first"]
+    on-repositories:
+      - owner: me
+        name: repo2
+    steps:
+      - name: "label"
+        runs:
+          - create-label:
+              name: "bug"
+              color: "f29513"
+              description: "Something isn't working"
+  - name: "first"
+    on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - name: "label"
+        runs:
+          - create-label:
+              name: "bug"
+              color: "f29513"
+              description: "Something isn't working"
+"#;
+
+    /// Run `BATCH`, whose first job fails, and describe the outcome of each job.
+    async fn run_with_policy(policy: DependencyFailure, calls: usize) -> Vec<String> {
+        let first = mock("POST", "/repos/me/repo1/labels")
+            .with_status(422)
+            .with_body(r#"{"message": "Validation Failed"}"#)
+            .expect(1)
+            .create();
+        let second = mock("POST", "/repos/me/repo2/labels")
+            .with_status(201)
+            .with_body(LABEL)
+            .expect(calls)
+            .create();
+        let third = mock("POST", "/repos/me/repo3/labels")
+            .with_status(201)
+            .with_body(LABEL)
+            .expect(calls)
+            .create();
+
+        let octomate = Octomate::with_options(
+            "test",
+            &ClientOptions {
+                base_url: Some(mockito::server_url()),
+                ..ClientOptions::default()
+            },
+        )
+        .await
+        .unwrap()
+        .with_settings(Settings {
+            on_dependency_failure: policy,
+            ..Settings::default()
+        });
+        let batch = Batch::try_from(BATCH.as_bytes()).unwrap();
+        let results = octomate.run_batch(&batch).await;
+
+        first.assert();
+        second.assert();
+        third.assert();
+        results
+            .iter()
+            .map(|job| match &job[0][0][0] {
+                Outcome::Succeeded(_) => "succeeded".to_string(),
+                Outcome::Failed(_) => "failed".to_string(),
+                Outcome::Skipped(reason) => reason.clone(),
+                Outcome::DryRun(_) => panic!("unexpected dry-run"),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_skip_on_dependency_failure() {
+        assert_eq!(
+            run_with_policy(DependencyFailure::Skip, 0).await,
+            vec![
+                "needed job first failed",
+                "needed job first failed",
+                "failed"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_on_dependency_failure() {
+        assert_eq!(
+            run_with_policy(DependencyFailure::Run, 1).await,
+            vec!["succeeded", "succeeded", "failed"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_abort_on_dependency_failure() {
+        assert_eq!(
+            run_with_policy(DependencyFailure::Abort, 0).await,
+            vec![
+                "batch aborted: needed job first failed",
+                "batch aborted: needed job first failed",
+                "failed"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_fields() {
+        let batch = r#"
+version: "1.0"
+jobs:
+  - name: issues
+    on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - create-issue:
+              titel: "A typo"
+              title: "A title"
+              body: "A body"
+              milestone: ~
+"#
+        .as_bytes();
+
+        match Batch::load(batch, UnknownFields::Deny) {
+            Err(Error::UnknownField { field, location }) => {
+                assert_eq!(field, "titel");
+                assert_eq!(location, "batch.jobs[0].steps[0].runs[0].create-issue");
+            }
+            _ => panic!("expected the unknown field to be reported"),
+        }
+        assert!(Batch::try_from(batch).is_err());
+
+        let batch = Batch::load(batch, UnknownFields::Ignore).unwrap();
+        assert_eq!(batch.jobs[0].name.as_deref(), Some("issues"));
+    }
+
+    #[test]
+    fn test_repository_groups() {
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+repository-groups:
+  frontend:
+    - owner: me
+      name: web
+    - owner: me
+      name: design
+  infra:
+    - owner: me
+      name: design
+    - owner: me
+      name: terraform
+    - owner: me
+      name: legacy
+jobs:
+  - on-repositories:
+      - owner: me
+        name: web
+      - owner: me
+        name: docs
+    on-repository-groups: [frontend, infra]
+    exclude-repositories:
+      - owner: me
+        name: legacy
+    steps: []
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let names: Vec<&str> = batch.jobs[0]
+            .on_repositories
+            .iter()
+            .map(|repository| repository.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["web", "docs", "design", "terraform"]);
+        assert_eq!(batch.jobs[0].on_repository_groups, None);
+
+        let unknown = Batch::try_from(
+            "version: \"1.0\"\njobs:\n  - on-repository-groups: [backend]\n    steps: []\n"
+                .as_bytes(),
+        );
+        assert!(matches!(unknown, Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn test_invalid_needs() {
+        let unknown = Batch::try_from(
+            "version: \"1.0\"\njobs:\n  - name: a\n    needs: [b]\n    on-repositories: []\n    steps: []\n"
+                .as_bytes(),
+        );
+        assert!(matches!(unknown, Err(Error::Validation(_))));
+
+        let circular = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - name: a
+    needs: [b]
+    on-repositories: []
+    steps: []
+  - name: b
+    needs: [a]
+    on-repositories: []
+    steps: []
+"#
+            .as_bytes(),
+        );
+        assert!(matches!(circular, Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn test_confirm_repository_count() {
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+      - owner: me
+        name: repo2
+    steps: []
+  - on-repositories:
+      - owner: me
+        name: repo2
+      - owner: me
+        name: repo3
+    steps: []
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(batch.repository_count(), 3);
+        assert!(batch.confirm_repository_count(3).is_ok());
+        assert!(matches!(
+            batch.confirm_repository_count(4),
+            Err(Error::UnexpectedRepositoryCount {
+                expected: 4,
+                actual: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_job_name() {
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - name: "labels"
+    on-repositories: []
+    steps: []
+  - on-repositories: []
+    steps: []
+  - name: "labels"
+    on-repositories: []
+    steps: []
+"#
+            .as_bytes(),
+        );
+        assert!(matches!(
+            batch,
+            Err(Error::DuplicateJobName { name }) if name == "labels"
+        ));
+    }
+
+    #[test]
+    fn test_require_repositories() {
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+repository-groups:
+  backend:
+    - owner: me
+      name: api
+    - owner: me
+      name: worker
+jobs:
+  - name: Label the backend
+    on-repository-groups:
+      - backend
+    exclude-repositories:
+      - owner: me
+        name: api
+      - owner: me
+        name: worker
+    steps:
+      - runs:
+          - delete-label:
+              name: wontfix
+  - name: Create the team
+    steps:
+      - runs:
+          - create-team:
+              name: Avengers
+              owner: me
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let empty: Vec<_> = batch
+            .jobs_without_repositories()
+            .map(|job| job.name.as_deref())
+            .collect();
+        assert_eq!(empty, vec![Some("Label the backend")]);
+        assert!(matches!(
+            batch.require_repositories(),
+            Err(Error::NoRepositoriesTargeted)
+        ));
+    }
+
+    #[test]
+    fn test_dump_effective_batch() {
+        let batch = Batch::try_from(
+            r#"{
+                "version": "1.0",
+                "jobs": [{
+                    "name": "labels",
+                    "on-repositories": [{"owner": "me", "name": "repo1"}],
+                    "steps": [{"runs": [
+                        {"create-label": {"name": "bug", "color": "f29513", "description": "Bug"}},
+                        {"create-branch": {"name": "feature", "from-ref": "main"}}
+                    ]}]
+                }]
+            }"#
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let dumped = batch.to_yaml().unwrap();
+        assert_eq!(
+            dumped,
+            r#"---
+version: "1.0"
+jobs:
+  - name: labels
+    on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - create-label:
+              name: bug
+              color: f29513
+              description: Bug
+          - create-branch:
+              name: feature
+              from-ref: main
+"#
+        );
+        assert_eq!(Batch::try_from(dumped.as_bytes()).unwrap(), batch);
+    }
+
+    #[tokio::test]
+    async fn test_extra_headers() {
+        let m = mock("POST", "/repos/me/repo1/labels")
+            .match_header("x-github-sso", "partial-results; organizations=21955855")
+            .match_header("x-proxy-tenant", "octo")
+            .with_status(201)
+            .with_body(LABEL)
+            .expect(1)
+            .create();
+
+        let headers = vec![
+            parse_header("X-Github-SSO: partial-results; organizations=21955855").unwrap(),
+            parse_header("x-proxy-tenant:octo").unwrap(),
+        ];
+        let octomate = Octomate::with_options(
+            "test",
+            &ClientOptions {
+                base_url: Some(mockito::server_url()),
+                headers,
+            },
+        )
+        .await
+        .unwrap();
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - create-label:
+              name: "bug"
+              color: "f29513"
+              description: "Something isn't working"
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate.run_batch(&batch).await;
+        assert!(matches!(results[0][0][0][0], Outcome::Succeeded(_)));
+        m.assert();
+
+        assert!(parse_header("no separator").is_err());
+        assert!(parse_header("bad name: value").is_err());
+        assert!(parse_header("x-bad-value: line\nbreak").is_err());
+    }
+
+    /// Start a server creating labels, whose requests stay in flight a while so the ones sent
+    /// at once overlap. Returns its url and the maximum number of requests in flight at once.
+    async fn start_slow_server() -> (String, Arc<AtomicUsize>) {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let counters = (in_flight.clone(), max_in_flight.clone());
+        let make_service = make_service_fn(move |_| {
+            let (in_flight, max_in_flight) = counters.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |_request| {
+                    let (in_flight, max_in_flight) = (in_flight.clone(), max_in_flight.clone());
+                    async move {
+                        let count = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_in_flight.fetch_max(count, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        Ok::<_, Infallible>(
+                            Response::builder()
+                                .status(201)
+                                .body(Body::from(LABEL))
+                                .unwrap(),
+                        )
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let address = server.local_addr();
+        tokio::spawn(server);
+        (format!("http://{}", address), max_in_flight)
+    }
+
+    #[tokio::test]
+    async fn test_max_parallel_jobs() {
+        let (url, max_in_flight) = start_slow_server().await;
+
+        let octomate = Octomate::with_options(
+            "test",
+            &ClientOptions {
+                base_url: Some(url),
+                ..ClientOptions::default()
+            },
+        )
+        .await
+        .unwrap()
+        .with_settings(Settings {
+            max_parallel_jobs: Some(2),
+            ..Settings::default()
+        });
+        let job = |name: &str| {
+            format!(
+                r#"
+  - name: {}
+    on-repositories:
+      - owner: me
+        name: {}
+    steps:
+      - runs:
+          - create-label:
+              name: bug
+              color: "f29513"
+              description: bug"#,
+                name, name
+            )
+        };
+        let batch = format!(
+            "version: \"1.0\"\njobs:{}{}{}{}\n",
+            job("repo1"),
+            job("repo2"),
+            job("repo3"),
+            job("repo4")
+        );
+        let batch = Batch::try_from(batch.as_bytes()).unwrap();
+        let results = octomate.run_batch(&batch).await;
+
+        assert!(results
+            .iter()
+            .all(|job| matches!(job[0][0][0], Outcome::Succeeded(_))));
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    /// A line of newline-delimited JSON jobs, creating a label on the repositories.
+    fn streamed_job(name: &str, repositories: &[&str]) -> String {
+        let repositories: Vec<_> = repositories
+            .iter()
+            .map(|repo| serde_json::json!({ "owner": "me", "name": repo }))
+            .collect();
+        serde_json::json!({
+            "name": name,
+            "on-repositories": repositories,
+            "steps": [{ "runs": [{ "create-label": {
+                "name": "bug",
+                "color": "f29513",
+                "description": "Something isn't working"
+            }}]}]
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_run_jobs_from_reader() {
+        let first = mock("POST", "/repos/me/stream1/labels")
+            .with_status(201)
+            .with_body(LABEL)
+            .expect(1)
+            .create();
+        let second = mock("POST", "/repos/me/stream2/labels")
+            .with_status(201)
+            .with_body(LABEL)
+            .expect(1)
+            .create();
+
+        let job = |repo| streamed_job(repo, &[repo]);
+        let destructive = serde_json::json!({
+            "name": "destructive",
+            "on-repositories": [{ "owner": "me", "name": "stream3" }],
+            "steps": [{ "runs": [{ "set-repository-visibility": { "visibility": "public" } }] }]
+        });
+        let input = format!(
+            "{}\n\n{}\nnot a job\n{}\n",
+            job("stream1"),
+            job("stream2"),
+            destructive
+        );
+
+        let octomate = Octomate::with_options(
+            "test",
+            &ClientOptions {
+                base_url: Some(mockito::server_url()),
+                ..ClientOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+        let mut completed = vec![];
+        let summary = octomate
+            .run_jobs_from_reader(
+                "stdin",
+                input.as_bytes(),
+                UnknownFields::Deny,
+                |batch| match batch.destructive_commands().is_empty() {
+                    true => Ok(()),
+                    false => Err("destructive".to_string()),
+                },
+                |batch, _| completed.push(batch.jobs[0].name.clone().unwrap()),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(summary.total.succeeded, 2);
+        assert_eq!(summary.total.failed, 2);
+        assert_eq!(summary.by_command["create-label"].succeeded, 2);
+        assert!(summary.failures[0].starts_with("line 4: "));
+        assert_eq!(summary.failures[1], "line 5: destructive");
+        completed.sort();
+        assert_eq!(completed, ["stream1", "stream2"]);
+        first.assert();
+        second.assert();
+    }
+
+    #[tokio::test]
+    async fn test_streamed_jobs_share_max_concurrency() {
+        let (url, max_in_flight) = start_slow_server().await;
+        let octomate = Octomate::with_options(
+            "test",
+            &ClientOptions {
+                base_url: Some(url),
+                ..ClientOptions::default()
+            },
+        )
+        .await
+        .unwrap()
+        .with_settings(Settings {
+            max_parallel_jobs: Some(4),
+            max_concurrency: Some(2),
+            ..Settings::default()
+        });
+        let input: String = (1..=4)
+            .map(|job| {
+                let name = format!("job{}", job);
+                streamed_job(&name, &["repo1", "repo2"]) + "\n"
+            })
+            .collect();
+        let summary = octomate
+            .run_jobs_from_reader(
+                "stdin",
+                input.as_bytes(),
+                UnknownFields::Deny,
+                |_| Ok(()),
+                |_, _| {},
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(summary.total.succeeded, 8);
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_jobs_fail_fast() {
+        let failed = mock("POST", "/repos/me/stream-failing/labels")
+            .with_status(422)
+            .with_body(r#"{"message": "Validation Failed"}"#)
+            .expect(1)
+            .create();
+        let next = mock("POST", "/repos/me/stream-next/labels")
+            .with_status(201)
+            .with_body(LABEL)
+            .expect(0)
+            .create();
+
+        let octomate = Octomate::with_options(
+            "test",
+            &ClientOptions {
+                base_url: Some(mockito::server_url()),
+                ..ClientOptions::default()
+            },
+        )
+        .await
+        .unwrap()
+        .with_settings(Settings {
+            max_parallel_jobs: Some(1),
+            fail_fast: true,
+            ..Settings::default()
+        });
+        let input = format!(
+            "{}\n{}\n",
+            streamed_job("failing", &["stream-failing"]),
+            streamed_job("next", &["stream-next"])
+        );
+        let summary = octomate
+            .run_jobs_from_reader(
+                "stdin",
+                input.as_bytes(),
+                UnknownFields::Deny,
+                |_| Ok(()),
+                |_, _| {},
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(summary.total.failed, 1);
+        assert_eq!(summary.total.succeeded, 0);
+        failed.assert();
+        next.assert();
+    }
 }
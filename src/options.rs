@@ -1,14 +1,356 @@
+use crate::notify::NotifyOn;
+use crate::octomate::DependencyFailure;
+use crate::output::OutputTemplate;
+use crate::summary::FailureThreshold;
 use clap::Parser;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 pub struct Options {
-    #[clap(long, help = "The batch file to run")]
-    pub batch_file: String,
+    #[clap(
+        long,
+        required_unless_present_any = &["batch-dir", "rollback", "jobs-from-stdin"],
+        help = "The batch file to run, either a local path or an HTTP(S) URL"
+    )]
+    pub batch_file: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "DIR",
+        conflicts_with = "batch-file",
+        help = "Run every batch file (*.yml, *.yaml, *.json) of DIR in lexicographic order"
+    )]
+    pub batch_dir: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Save the actions undoing the commands run by the batch to FILE"
+    )]
+    pub rollback_file: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = &["batch-file", "batch-dir"],
+        help = "Undo the commands saved in the rollback FILE, in reverse order"
+    )]
+    pub rollback: Option<String>,
+
+    #[clap(
+        long,
+        conflicts_with_all = &["batch-file", "batch-dir", "rollback"],
+        help = "Run the jobs read from stdin as newline-delimited JSON, each one as it arrives"
+    )]
+    pub jobs_from_stdin: bool,
+
+    #[clap(long, help = "Validate and lint the batch file without running it")]
+    pub validate: bool,
+
+    #[clap(
+        long,
+        help = "Print the batch as it would run, as YAML, without running it"
+    )]
+    pub dump_effective: bool,
+
+    #[clap(
+        long,
+        help = "Print the jobs and their needs as a Graphviz DOT graph, without running them"
+    )]
+    pub print_plan_graph: bool,
+
+    #[clap(long, help = "Treat lint warnings as errors")]
+    pub strict: bool,
+
+    #[clap(
+        long,
+        help = "Ignore the fields of the batch file unknown to this version of octomate"
+    )]
+    pub lenient: bool,
+
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Abort unless the batch targets exactly N distinct repositories"
+    )]
+    pub confirm_count: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Abort if the batch targets no repository once its groups and exclusions are expanded"
+    )]
+    pub require_repos: bool,
+
+    #[clap(
+        long,
+        help = "Ask before each step whether to proceed, skip it or abort the batch"
+    )]
+    pub interactive: bool,
+
+    #[clap(long, help = "Describe the commands instead of running them")]
+    pub dry_run: bool,
+
+    #[clap(short, long, help = "Don't show the progress bar of the batches")]
+    pub quiet: bool,
+
+    #[clap(
+        long,
+        help = "Run the commands that can't be undone, like making a repository public, without asking"
+    )]
+    pub allow_destructive: bool,
+
+    #[clap(
+        long,
+        help = "Skip the deletions of things already missing instead of reporting them as failed"
+    )]
+    pub ignore_missing: bool,
+
+    #[clap(
+        long,
+        help = "Append a footer naming the batch to the bodies of the issues and gists it creates"
+    )]
+    pub stamp_provenance: bool,
+
+    #[clap(
+        long,
+        value_name = "FORMAT",
+        help = "Format of the provenance footer, {batch} and {timestamp} are replaced"
+    )]
+    pub provenance_format: Option<String>,
+
+    #[clap(
+        long,
+        help = "Check that the assignees of the issues can be assigned in each repository"
+    )]
+    pub validate_assignees: bool,
+
+    #[clap(
+        long,
+        value_name = "SECS",
+        value_parser = parse_request_timeout,
+        help = "Maximum duration of each request sent to Github, at least 1"
+    )]
+    pub request_timeout_secs: Option<u64>,
+
+    #[clap(
+        long,
+        value_name = "SECS",
+        default_value = "10",
+        help = "Delay between two checks of what a command waits for, like a workflow run"
+    )]
+    pub poll_interval_secs: u64,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value = "skip",
+        help = "What to do with a job when a job it needs failed"
+    )]
+    pub on_dependency_failure: DependencyFailure,
+
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Maximum number of jobs running at once, unlimited by default"
+    )]
+    pub max_parallel_jobs: Option<usize>,
+
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Maximum number of commands running at once, counting each repository, unlimited by default"
+    )]
+    pub max_concurrency: Option<usize>,
+
+    #[clap(long, help = "Skip every command not started yet once one failed")]
+    pub fail_fast: bool,
+
+    #[clap(
+        long,
+        value_name = "MS",
+        default_value = "500",
+        help = "Delay before the first retry, doubled on each retry"
+    )]
+    pub retry_base_ms: u64,
+
+    #[clap(
+        long,
+        value_name = "MS",
+        default_value = "30000",
+        help = "Upper bound of the delay between two retries"
+    )]
+    pub retry_max_ms: u64,
+
+    #[clap(
+        long,
+        value_name = "FRACTION",
+        default_value = "0.5",
+        help = "Fraction of the retry delay picked at random, between 0 and 1"
+    )]
+    pub retry_jitter: f64,
+
+    #[clap(
+        long,
+        value_name = "SEED",
+        help = "Seed the random part of the retry delays, to retry the same way on each run"
+    )]
+    pub retry_seed: Option<u64>,
+
+    #[clap(
+        long = "header",
+        value_name = "NAME: VALUE",
+        value_parser = parse_header,
+        help = "Add a header to every request sent to Github, can be repeated"
+    )]
+    pub headers: Vec<(String, String)>,
+
+    #[clap(
+        long,
+        value_name = "DIR",
+        conflicts_with = "replay",
+        help = "Save each request sent to Github and its response in DIR"
+    )]
+    pub record: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "DIR",
+        help = "Answer the requests with the responses recorded in DIR instead of calling Github"
+    )]
+    pub replay: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "URL",
+        help = "POST a JSON summary of the batch to URL once it's finished"
+    )]
+    pub notify_url: Option<String>,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value = "failure",
+        help = "When to POST the summary to the notification URL"
+    )]
+    pub notify_on: NotifyOn,
+
+    #[clap(
+        long,
+        value_name = "N",
+        conflicts_with = "max-failure-rate",
+        help = "Exit successfully when at most N commands failed"
+    )]
+    pub max_failures: Option<usize>,
+
+    #[clap(
+        long,
+        value_name = "FRACTION",
+        value_parser = parse_failure_rate,
+        help = "Exit successfully when at most this fraction of the commands failed, between 0 and 1"
+    )]
+    pub max_failure_rate: Option<f64>,
+
+    #[clap(
+        long,
+        value_name = "TEMPLATE",
+        value_parser = parse_output_template,
+        help = "Print a line per command and repository, formatted like \"{repo} {command} {status} {url} {error}\""
+    )]
+    pub output_template: Option<OutputTemplate>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "When a command panics, write a JSON summary of the results so far to FILE, or stderr"
+    )]
+    pub dump_results_on_panic: Option<Option<String>>,
+}
+
+fn parse_output_template(template: &str) -> Result<OutputTemplate, String> {
+    OutputTemplate::parse(template).map_err(|err| err.to_string())
+}
+
+/// A rate of 5 is most likely meant as 5%, and would tolerate every failure.
+fn parse_failure_rate(rate: &str) -> Result<f64, String> {
+    let rate: f64 = rate
+        .parse()
+        .map_err(|err: std::num::ParseFloatError| err.to_string())?;
+    match (0.0..=1.0).contains(&rate) {
+        true => Ok(rate),
+        false => Err(format!("{} isn't between 0 and 1, like 0.05 for 5%", rate)),
+    }
+}
+
+fn parse_request_timeout(secs: &str) -> Result<u64, String> {
+    match secs.parse::<u64>() {
+        Ok(0) => Err("a timeout of 0 would fail every request".to_string()),
+        Ok(secs) => Ok(secs),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn parse_header(header: &str) -> Result<(String, String), String> {
+    crate::octomate::parse_header(header).map_err(|err| err.to_string())
 }
 
 impl Options {
     pub fn from_cli() -> Self {
         Options::parse()
     }
+
+    /// The failures tolerated before exiting with an error, none by default.
+    pub fn failure_threshold(&self) -> FailureThreshold {
+        match (self.max_failures, self.max_failure_rate) {
+            (_, Some(rate)) => FailureThreshold::Rate(rate),
+            (count, None) => FailureThreshold::Count(count.unwrap_or(0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_max_failure_rate_range() {
+        let rate = |rate: &str| {
+            let rate = format!("--max-failure-rate={}", rate);
+            Options::try_parse_from(["octomate", "--batch-file", "batch.yml", &rate])
+                .map(|options| options.max_failure_rate)
+                .map_err(|err| err.kind())
+        };
+        assert_eq!(rate("0.05"), Ok(Some(0.05)));
+        assert_eq!(rate("1"), Ok(Some(1.0)));
+        assert_eq!(rate("5"), Err(clap::ErrorKind::ValueValidation));
+        assert_eq!(rate("-0.1"), Err(clap::ErrorKind::ValueValidation));
+        assert_eq!(rate("much"), Err(clap::ErrorKind::ValueValidation));
+    }
+
+    #[test]
+    fn test_rollback_file_of_streamed_jobs() {
+        let options = Options::try_parse_from([
+            "octomate",
+            "--jobs-from-stdin",
+            "--rollback-file",
+            "rollback.json",
+        ])
+        .unwrap();
+        assert_eq!(options.rollback_file.as_deref(), Some("rollback.json"));
+        assert!(
+            Options::try_parse_from(["octomate", "--jobs-from-stdin", "--rollback", "r"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_request_timeout_range() {
+        let timeout = |secs: &str| {
+            let secs = format!("--request-timeout-secs={}", secs);
+            Options::try_parse_from(["octomate", "--batch-file", "batch.yml", &secs])
+                .map(|options| options.request_timeout_secs)
+                .map_err(|err| err.kind())
+        };
+        assert_eq!(timeout("30"), Ok(Some(30)));
+        assert_eq!(timeout("0"), Err(clap::ErrorKind::ValueValidation));
+        assert_eq!(timeout("-1"), Err(clap::ErrorKind::ValueValidation));
+    }
 }
@@ -0,0 +1,149 @@
+use crate::command::{Command, Outcome};
+use crate::error::Error;
+use crate::octomate::{Batch, BatchResult, Repository};
+
+/// Placeholders of an output template, replaced by the value of each outcome.
+pub const PLACEHOLDERS: [&str; 5] = ["repo", "command", "status", "url", "error"];
+
+/// A line per outcome in a custom format, like `{repo} {command} {status} {url}`,
+/// for log ingestion. The missing values are written `-`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputTemplate {
+    template: String,
+}
+
+impl OutputTemplate {
+    /// Fail on the first `{placeholder}` of `template` not in `PLACEHOLDERS`.
+    pub fn parse(template: &str) -> Result<Self, Error> {
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| Error::UnknownPlaceholder(rest[start..].to_string()))?;
+            let placeholder = &rest[start + 1..start + end];
+            if !PLACEHOLDERS.contains(&placeholder) {
+                return Err(Error::UnknownPlaceholder(placeholder.to_string()));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        Ok(Self {
+            template: template.to_string(),
+        })
+    }
+
+    /// The line of the outcome of `command` on `repository`.
+    pub fn render(
+        &self,
+        repository: Option<&Repository>,
+        command: &Command,
+        outcome: &Outcome,
+    ) -> String {
+        let (status, url, error) = match outcome {
+            Outcome::Succeeded(response) => ("succeeded", response.url(), None),
+            Outcome::Failed(err) => ("failed", None, Some(err.to_string())),
+            Outcome::Skipped(_) => ("skipped", None, None),
+            Outcome::DryRun(_) => ("dry-run", None, None),
+        };
+        let repo = repository.map(|repository| format!("{}/{}", repository.owner, repository.name));
+        let value = |placeholder: &str| match placeholder {
+            "repo" => repo.clone(),
+            "command" => Some(command.name().to_string()),
+            "status" => Some(status.to_string()),
+            "url" => url.clone(),
+            _ => error.clone(),
+        };
+
+        // Placeholders are replaced in a single pass, so the values are written as they are.
+        let mut line = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+        while let Some((start, end)) = rest
+            .find('{')
+            .and_then(|start| rest[start..].find('}').map(|end| (start, start + end)))
+        {
+            line.push_str(&rest[..start]);
+            line.push_str(&value(&rest[start + 1..end]).unwrap_or_else(|| "-".to_string()));
+            rest = &rest[end + 1..];
+        }
+        line.push_str(rest);
+        line
+    }
+
+    /// The lines of every outcome of the batch, in the order of its jobs, steps and commands.
+    pub fn lines(&self, batch: &Batch, results: &BatchResult) -> Vec<String> {
+        let mut lines = vec![];
+        for (job, job_results) in batch.jobs.iter().zip(results) {
+            for (step, step_results) in job.all_steps().zip(job_results) {
+                for (command, outcomes) in step.runs.iter().zip(step_results) {
+                    for (index, outcome) in outcomes.iter().enumerate() {
+                        lines.push(self.render(
+                            command.repository_of(job, index),
+                            command,
+                            outcome,
+                        ));
+                    }
+                }
+            }
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::Response;
+
+    #[test]
+    fn test_output_template() {
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+      - owner: me
+        name: repo2
+    steps:
+      - runs:
+          - add-team-repository:
+              org: me
+              team-slug: heroes
+          - delete-label:
+              name: wontfix
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let repository = |name: &str| Repository {
+            owner: "me".to_string(),
+            name: name.to_string(),
+        };
+        let results: BatchResult = vec![vec![vec![
+            vec![
+                Outcome::Succeeded(Response::AddTeamRepository(repository("repo1"))),
+                Outcome::Failed(Error::Validation("invalid".to_string())),
+            ],
+            vec![
+                Outcome::Skipped("not found".to_string()),
+                Outcome::DryRun("delete-label on me/repo2".to_string()),
+            ],
+        ]]];
+
+        let template = OutputTemplate::parse("{repo} {command} {status} {url} {error}").unwrap();
+        assert_eq!(
+            template.lines(&batch, &results),
+            vec![
+                "me/repo1 add-team-repository succeeded https://github.com/me/repo1 -",
+                "me/repo2 add-team-repository failed - Invalid batch: invalid",
+                "me/repo1 delete-label skipped - -",
+                "me/repo2 delete-label dry-run - -",
+            ]
+        );
+
+        assert!(matches!(
+            OutputTemplate::parse("{repo} {state}"),
+            Err(Error::UnknownPlaceholder(placeholder)) if placeholder == "state"
+        ));
+    }
+}
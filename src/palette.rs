@@ -0,0 +1,68 @@
+use crate::error::Error;
+
+/// Color derived from the name of the label.
+pub const AUTO: &str = "auto";
+
+/// The colors of the default labels of a Github repository.
+const PALETTE: [(&str, &str); 9] = [
+    ("bug", "d73a4a"),
+    ("documentation", "0075ca"),
+    ("duplicate", "cfd3d7"),
+    ("enhancement", "a2eeef"),
+    ("good first issue", "7057ff"),
+    ("help wanted", "008672"),
+    ("invalid", "e4e669"),
+    ("question", "d876e3"),
+    ("wontfix", "ffffff"),
+];
+
+/// The hex code of a label color, written either as a hex code, a palette name or `auto`.
+pub fn resolve(label: &str, color: &str) -> Result<String, Error> {
+    if color.eq_ignore_ascii_case(AUTO) {
+        return Ok(format!("{:06x}", fnv1a(label.as_bytes()) & 0xff_ffff));
+    }
+    if let Some((_, hex)) = PALETTE
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(color))
+    {
+        return Ok(hex.to_string());
+    }
+    if color.len() == 6 && color.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(color.to_string());
+    }
+    Err(Error::Validation(format!(
+        "color {} of label {} is neither a hex code, a palette name nor {}",
+        color, label, AUTO
+    )))
+}
+
+/// 64-bit FNV-1a, stable across Rust versions unlike the hasher of the standard library.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resolve_color() {
+        let color = resolve("needs-triage", "auto").unwrap();
+        assert_eq!(color, resolve("needs-triage", "AUTO").unwrap());
+        assert_eq!(color, "e324d1");
+        assert_ne!(color, resolve("needs-review", "auto").unwrap());
+
+        assert_eq!(
+            resolve("first-timers", "good first issue").unwrap(),
+            "7057ff"
+        );
+        assert_eq!(resolve("bug", "Bug").unwrap(), "d73a4a");
+        assert_eq!(resolve("bug", "B60205").unwrap(), "B60205");
+        assert!(matches!(
+            resolve("bug", "reddish"),
+            Err(Error::Validation(_))
+        ));
+    }
+}
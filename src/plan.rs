@@ -0,0 +1,130 @@
+use crate::octomate::{Batch, Job};
+use std::collections::HashMap;
+
+/// The jobs of the batch and their needs as a Graphviz DOT graph.
+/// Edges go from a job to the jobs needing it, in the order the jobs start.
+/// The needs making a cycle are highlighted in red.
+pub fn to_dot(batch: &Batch) -> String {
+    let ids: HashMap<&str, String> = batch
+        .jobs
+        .iter()
+        .filter_map(|job| job.name.as_deref())
+        .map(|name| (name, quote(name)))
+        .collect();
+    let id = |index: usize, job: &Job| match &job.name {
+        Some(name) => quote(name),
+        None => quote(&format!("#{}", index)),
+    };
+    let index_of = |job: &Job| batch.jobs.iter().position(|other| std::ptr::eq(other, job));
+
+    let mut dot = format!("digraph {} {{\n", quote(&batch.display_name()));
+    let (layers, waiting) = batch.topological_layers();
+    for layer in &layers {
+        let nodes: Vec<String> = layer
+            .iter()
+            .filter_map(|job| index_of(job).map(|index| id(index, job)))
+            .collect();
+        dot.push_str(&format!("  {{ rank = same; {}; }}\n", nodes.join("; ")));
+    }
+    for job in &waiting {
+        if let Some(index) = index_of(job) {
+            dot.push_str(&format!("  {} [color = red];\n", id(index, job)));
+        }
+    }
+
+    for (index, job) in batch.jobs.iter().enumerate() {
+        for need in job.needs() {
+            let from = ids
+                .get(need.as_str())
+                .cloned()
+                .unwrap_or_else(|| quote(need));
+            let in_cycle = job
+                .name
+                .as_deref()
+                .is_some_and(|name| needs_transitively(batch, need, name));
+            let style = if in_cycle { " [color = red]" } else { "" };
+            dot.push_str(&format!("  {} -> {}{};\n", from, id(index, job), style));
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Whether the job named `job` needs the job named `need`, directly or through other jobs.
+fn needs_transitively(batch: &Batch, job: &str, need: &str) -> bool {
+    let jobs: HashMap<&str, &Job> = batch
+        .jobs
+        .iter()
+        .filter_map(|job| job.name.as_deref().map(|name| (name, job)))
+        .collect();
+    let mut visited = vec![];
+    let mut stack = vec![job];
+    while let Some(name) = stack.pop() {
+        if visited.contains(&name) {
+            continue;
+        }
+        visited.push(name);
+        for next in jobs.get(name).into_iter().flat_map(|job| job.needs()) {
+            if next == need {
+                return true;
+            }
+            stack.push(next);
+        }
+    }
+    false
+}
+
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::octomate::UnknownFields;
+
+    #[test]
+    fn test_plan_graph() {
+        let batch = Batch::parse(
+            r#"
+version: "1.0"
+name: release
+jobs:
+  - name: build
+    on-repositories: []
+    steps: []
+  - name: test
+    needs: [build]
+    on-repositories: []
+    steps: []
+  - name: publish
+    needs: [build, test, announce]
+    on-repositories: []
+    steps: []
+  - name: announce
+    needs: [publish]
+    on-repositories: []
+    steps: []
+"#
+            .as_bytes(),
+            UnknownFields::Deny,
+        )
+        .unwrap();
+
+        assert_eq!(
+            to_dot(&batch),
+            r#"digraph "release" {
+  { rank = same; "build"; }
+  { rank = same; "test"; }
+  "publish" [color = red];
+  "announce" [color = red];
+  "build" -> "test";
+  "build" -> "publish";
+  "test" -> "publish";
+  "announce" -> "publish" [color = red];
+  "publish" -> "announce" [color = red];
+}
+"#
+        );
+    }
+}
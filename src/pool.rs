@@ -0,0 +1,99 @@
+use crate::error::Error;
+use crate::octomate::ClientOptions;
+use hyper::header::HeaderName;
+use octocrab::Octocrab;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+/// Identify a client of the pool by the API it targets and the credentials it uses.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClientKey {
+    /// Base url of the API, `None` for `https://api.github.com`.
+    pub host: Option<String>,
+    /// Fingerprint of the token, so the pool doesn't keep another copy of it.
+    pub auth: u64,
+}
+
+impl ClientKey {
+    pub fn new(host: Option<&str>, personal_token: &str) -> Self {
+        let mut hasher = DefaultHasher::new();
+        personal_token.hash(&mut hasher);
+        Self {
+            host: host.map(|host| host.trim_end_matches('/').to_string()),
+            auth: hasher.finish(),
+        }
+    }
+}
+
+/// The API clients of a run, built once per host and credentials,
+/// so Github and a Github Enterprise server, or several installations, can be used side by side.
+/// Every client sends the extra headers of the options.
+#[derive(Default)]
+pub struct ClientPool {
+    headers: Vec<(String, String)>,
+    clients: Mutex<HashMap<ClientKey, Arc<Octocrab>>>,
+}
+
+impl ClientPool {
+    pub fn new(options: &ClientOptions) -> Self {
+        Self {
+            headers: options.headers.clone(),
+            clients: Mutex::default(),
+        }
+    }
+
+    /// The client for `host` authenticated with `personal_token`, built on first use.
+    pub fn client(&self, host: Option<&str>, personal_token: &str) -> Result<Arc<Octocrab>, Error> {
+        let key = ClientKey::new(host, personal_token);
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(client) = clients.get(&key) {
+            return Ok(client.clone());
+        }
+
+        let mut builder = Octocrab::builder().personal_token(personal_token.to_string());
+        if let Some(host) = host {
+            builder = builder.base_url(host)?;
+        }
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(hyper::http::Error::from)?;
+            builder = builder.add_header(name, value.clone());
+        }
+        let client = Arc::new(builder.build()?);
+        clients.insert(key, client.clone());
+        Ok(client)
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_distinct_clients() {
+        let pool = ClientPool::default();
+        let github = pool.client(None, "ghp_first").unwrap();
+        let enterprise = pool
+            .client(Some("https://github.example.com/api/v3/"), "ghp_first")
+            .unwrap();
+        let installation = pool.client(None, "ghs_second").unwrap();
+        assert!(!Arc::ptr_eq(&github, &enterprise));
+        assert!(!Arc::ptr_eq(&github, &installation));
+        assert_eq!(pool.len(), 3);
+
+        let reused = pool
+            .client(Some("https://github.example.com/api/v3"), "ghp_first")
+            .unwrap();
+        assert!(Arc::ptr_eq(&enterprise, &reused));
+        assert_eq!(pool.len(), 3);
+    }
+}
@@ -0,0 +1,211 @@
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Width of the bar itself, without the counts and the ETA.
+const WIDTH: usize = 30;
+
+/// A progress bar of the commands run on each repository, redrawn on a single line.
+/// Each draw clears the line first and leaves the cursor at its start,
+/// so the lines logged meanwhile overwrite the bar instead of being appended to it.
+pub struct Progress {
+    output: Mutex<Box<dyn Write + Send>>,
+    total: AtomicUsize,
+    completed: AtomicUsize,
+    started: Mutex<Instant>,
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Progress")
+            .field("total", &self.total)
+            .field("completed", &self.completed)
+            .finish()
+    }
+}
+
+impl Progress {
+    pub fn new(output: impl Write + Send + 'static) -> Self {
+        Self {
+            output: Mutex::new(Box::new(output)),
+            total: AtomicUsize::new(0),
+            completed: AtomicUsize::new(0),
+            started: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Draw on stderr, or `None` if it isn't a terminal.
+    pub fn from_tty() -> Option<Self> {
+        std::io::stderr()
+            .is_terminal()
+            .then(|| Self::new(std::io::stderr()))
+    }
+
+    /// Start over for a batch of `total` units, see `Batch::unit_count`.
+    pub fn start(&self, total: usize) {
+        self.total.store(total, Ordering::SeqCst);
+        self.completed.store(0, Ordering::SeqCst);
+        *self.started.lock().unwrap() = Instant::now();
+        self.draw();
+    }
+
+    /// Count `count` more units to complete, for the jobs streamed as they come.
+    pub fn extend(&self, count: usize) {
+        self.total.fetch_add(count, Ordering::SeqCst);
+        self.draw();
+    }
+
+    /// Count one more completed unit.
+    pub fn advance(&self) {
+        self.completed.fetch_add(1, Ordering::SeqCst);
+        self.draw();
+    }
+
+    /// Clear the bar, before logging the results.
+    pub fn finish(&self) {
+        let mut output = self.output.lock().unwrap();
+        write!(output, "\r\x1b[2K").ok();
+        output.flush().ok();
+    }
+
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::SeqCst)
+    }
+
+    pub fn completed(&self) -> usize {
+        self.completed.load(Ordering::SeqCst)
+    }
+
+    fn draw(&self) {
+        let line = render(
+            self.completed(),
+            self.total(),
+            self.started.lock().unwrap().elapsed(),
+        );
+        let mut output = self.output.lock().unwrap();
+        write!(output, "\r\x1b[2K{}\r", line).ok();
+        output.flush().ok();
+    }
+}
+
+/// The bar for `completed` units out of `total`, with the time left at the pace so far.
+fn render(completed: usize, total: usize, elapsed: Duration) -> String {
+    let filled = match total {
+        0 => WIDTH,
+        total => WIDTH * completed.min(total) / total,
+    };
+    let eta = match completed {
+        0 => "--".to_string(),
+        completed => {
+            let left =
+                elapsed.as_secs_f64() * total.saturating_sub(completed) as f64 / completed as f64;
+            let left = left.round() as u64;
+            format!("{}m{:02}s", left / 60, left % 60)
+        }
+    };
+    format!(
+        "[{}{}] {}/{} ETA {}",
+        "=".repeat(filled),
+        " ".repeat(WIDTH - filled),
+        completed,
+        total,
+        eta
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::octomate::{Batch, Octomate, Settings};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_progress_of_streamed_jobs() {
+        let progress = Arc::new(Progress::new(std::io::sink()));
+        progress.start(0);
+        let octomate = {
+            let progress = progress.clone();
+            Octomate::new("test")
+                .await
+                .unwrap()
+                .with_settings(Settings {
+                    dry_run: true,
+                    ..Settings::default()
+                })
+                .on_command_complete(move |_, _| progress.advance())
+        };
+        let job = r#"{"on-repositories": [{"owner": "me", "name": "repo1"}, {"owner": "me", "name": "repo2"}], "steps": [{"runs": [{"delete-label": {"name": "bug"}}]}]}"#;
+        let input = format!("{}\n{}\n", job, job);
+        octomate
+            .run_jobs_from_reader(
+                "stdin",
+                input.as_bytes(),
+                crate::octomate::UnknownFields::Deny,
+                |batch| {
+                    progress.extend(batch.unit_count());
+                    Ok(())
+                },
+                |_, _| {},
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(progress.total(), 4);
+        assert_eq!(progress.completed(), progress.total());
+    }
+
+    #[tokio::test]
+    async fn test_progress_total_is_the_unit_count() {
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+      - owner: me
+        name: repo2
+      - owner: me
+        name: repo3
+    steps:
+      - runs:
+          - delete-label:
+              name: bug
+          - create-team:
+              name: Avengers
+              owner: me
+  - on-repositories: []
+    steps:
+      - runs:
+          - delete-label:
+              name: bug
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(batch.unit_count(), 4);
+
+        let progress = Arc::new(Progress::new(std::io::sink()));
+        progress.start(batch.unit_count());
+        let octomate = {
+            let progress = progress.clone();
+            Octomate::new("test")
+                .await
+                .unwrap()
+                .with_settings(Settings {
+                    dry_run: true,
+                    ..Settings::default()
+                })
+                .on_command_complete(move |_, _| progress.advance())
+        };
+        octomate.run_batch(&batch).await;
+
+        assert_eq!(progress.total(), 4);
+        assert_eq!(progress.completed(), progress.total());
+        assert_eq!(
+            render(1, 4, Duration::from_secs(30)),
+            "[=======                       ] 1/4 ETA 1m30s"
+        );
+    }
+}
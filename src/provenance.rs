@@ -0,0 +1,81 @@
+use crate::octomate::Context;
+use chrono::{SecondsFormat, Utc};
+
+/// Footer appended to the bodies created by a batch, `{batch}` and `{timestamp}` are replaced
+/// by the name of the batch and the time of the run.
+pub const DEFAULT_FORMAT: &str = "Created by octomate batch '{batch}' at {timestamp}";
+
+/// Whether the batch stamps what it creates, the batch overriding `--stamp-provenance`.
+pub fn is_enabled(ctx: &Context<'_>) -> bool {
+    ctx.batch
+        .stamp_provenance
+        .unwrap_or(ctx.settings.stamp_provenance)
+}
+
+/// The provenance footer for the current time.
+pub fn footer(ctx: &Context<'_>) -> String {
+    let format = ctx
+        .settings
+        .provenance_format
+        .as_deref()
+        .unwrap_or(DEFAULT_FORMAT);
+    format
+        .replace("{batch}", ctx.batch.name.as_deref().unwrap_or("unnamed"))
+        .replace(
+            "{timestamp}",
+            &Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        )
+}
+
+/// `text` followed by the provenance footer when enabled, `text` as it is otherwise.
+pub fn stamp(ctx: &Context<'_>, text: &str) -> String {
+    if !is_enabled(ctx) {
+        return text.to_string();
+    }
+    match text.is_empty() {
+        true => footer(ctx),
+        false => format!("{}\n\n{}", text, footer(ctx)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cache::RepositoryCache;
+    use crate::octomate::{Batch, Settings};
+
+    #[test]
+    fn test_stamp_only_when_enabled() {
+        let mut batch = Batch::try_from(
+            r#"
+version: "1.0"
+name: cleanup
+jobs: []
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let cache = RepositoryCache::default();
+        let disabled = Settings::default();
+        let enabled = Settings {
+            stamp_provenance: true,
+            provenance_format: Some("-- {batch}".to_string()),
+            ..Settings::default()
+        };
+
+        let ctx = Context::new(&batch, &disabled, &cache, None, None);
+        assert_eq!(stamp(&ctx, "body"), "body");
+
+        let ctx = Context::new(&batch, &enabled, &cache, None, None);
+        assert_eq!(stamp(&ctx, "body"), "body\n\n-- cleanup");
+        assert_eq!(stamp(&ctx, ""), "-- cleanup");
+
+        batch.stamp_provenance = Some(false);
+        let ctx = Context::new(&batch, &enabled, &cache, None, None);
+        assert_eq!(stamp(&ctx, "body"), "body");
+
+        batch.stamp_provenance = Some(true);
+        let ctx = Context::new(&batch, &disabled, &cache, None, None);
+        assert!(stamp(&ctx, "body").starts_with("body\n\nCreated by octomate batch 'cleanup' at "));
+    }
+}
@@ -0,0 +1,382 @@
+use crate::error::Error;
+use crate::io::HttpClient;
+use hyper::body::Bytes;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::fs;
+
+/// Request headers not forwarded to the upstream API while recording: the ones about
+/// the connection to the proxy, and `host`, set for the upstream API instead.
+const UNFORWARDED_HEADERS: [&str; 9] = [
+    "connection",
+    "host",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Response headers saved in the fixtures.
+const RECORDED_HEADERS: [&str; 2] = ["content-type", "link"];
+
+/// A request/response exchange saved to disk.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fixture {
+    pub method: String,
+    pub path: String,
+    pub request_body: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// Start a local proxy forwarding every request to `upstream`
+/// and saving each exchange in `dir`.
+/// Returns the address the proxy is listening on.
+pub async fn start_recorder(
+    http: &HttpClient,
+    dir: impl Into<PathBuf>,
+    upstream: impl Into<String>,
+) -> Result<SocketAddr, Error> {
+    let dir = dir.into();
+    fs::create_dir_all(&dir).await?;
+    let recorder = Arc::new(Recorder {
+        dir,
+        upstream: upstream.into().trim_end_matches('/').to_string(),
+        counter: AtomicUsize::new(0),
+        client: http.clone(),
+    });
+
+    let make_service = make_service_fn(move |_| {
+        let recorder = recorder.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let recorder = recorder.clone();
+                async move { Ok::<_, Infallible>(recorder.handle(request).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&local_address())?.serve(make_service);
+    let address = server.local_addr();
+    tokio::spawn(server);
+    Ok(address)
+}
+
+/// Start a local server answering the requests with the fixtures saved in `dir`.
+/// Fixtures are served in the order they were recorded for a given method and path.
+/// Returns the address the server is listening on.
+pub async fn start_replayer(dir: impl AsRef<Path>) -> Result<SocketAddr, Error> {
+    let replayer = Arc::new(Replayer {
+        fixtures: Mutex::new(load_fixtures(dir).await?),
+    });
+
+    let make_service = make_service_fn(move |_| {
+        let replayer = replayer.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let replayer = replayer.clone();
+                async move { Ok::<_, Infallible>(replayer.handle(request)) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&local_address())?.serve(make_service);
+    let address = server.local_addr();
+    tokio::spawn(server);
+    Ok(address)
+}
+
+fn local_address() -> SocketAddr {
+    SocketAddr::from(([127, 0, 0, 1], 0))
+}
+
+async fn load_fixtures(dir: impl AsRef<Path>) -> Result<Vec<Fixture>, Error> {
+    let mut paths = vec![];
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut fixtures = vec![];
+    for path in paths {
+        let bytes = fs::read(path).await?;
+        fixtures.push(serde_json::from_slice(&bytes)?);
+    }
+    Ok(fixtures)
+}
+
+fn path_and_query(request: &Request<Body>) -> String {
+    request
+        .uri()
+        .path_and_query()
+        .map(|path_and_query| path_and_query.to_string())
+        .unwrap_or_else(|| "/".to_string())
+}
+
+fn error_response(status: StatusCode, message: impl std::fmt::Display) -> Response<Body> {
+    let body = serde_json::json!({ "message": message.to_string() }).to_string();
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+struct Recorder {
+    dir: PathBuf,
+    upstream: String,
+    counter: AtomicUsize,
+    client: HttpClient,
+}
+
+impl Recorder {
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        let host = request
+            .headers()
+            .get("host")
+            .and_then(|host| host.to_str().ok())
+            .map(|host| format!("http://{}", host));
+        match self.forward(request).await {
+            Ok(mut fixture) => {
+                // Keep the pagination going through the proxy.
+                if let Some(host) = host {
+                    for (name, value) in fixture.headers.iter_mut() {
+                        if name == "link" {
+                            *value = value.replace(&self.upstream, &host);
+                        }
+                    }
+                }
+                let response = Self::respond(&fixture);
+                if let Err(err) = self.save(&fixture).await {
+                    return error_response(StatusCode::INTERNAL_SERVER_ERROR, err);
+                }
+                response
+            }
+            Err(err) => error_response(StatusCode::BAD_GATEWAY, err),
+        }
+    }
+
+    async fn forward(&self, request: Request<Body>) -> Result<Fixture, Error> {
+        let method = request.method().clone();
+        let path = path_and_query(&request);
+
+        let mut upstream_request = Request::builder()
+            .method(method.clone())
+            .uri(format!("{}{}", self.upstream, path));
+        for (name, value) in request.headers() {
+            if !UNFORWARDED_HEADERS.contains(&name.as_str()) {
+                upstream_request = upstream_request.header(name, value);
+            }
+        }
+
+        let request_body = hyper::body::to_bytes(request.into_body()).await?;
+        let upstream_request = upstream_request.body(Body::from(request_body.clone()))?;
+        let response = self.client.request(upstream_request).await?;
+
+        let status = response.status().as_u16();
+        let headers = RECORDED_HEADERS
+            .iter()
+            .filter_map(|name| {
+                let value = response.headers().get(*name)?.to_str().ok()?;
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+
+        Ok(Fixture {
+            method: method.to_string(),
+            path,
+            request_body: String::from_utf8_lossy(&request_body).into_owned(),
+            status,
+            headers,
+            body: String::from_utf8_lossy(&body).into_owned(),
+        })
+    }
+
+    async fn save(&self, fixture: &Fixture) -> Result<(), Error> {
+        let index = self.counter.fetch_add(1, Ordering::SeqCst);
+        let path = self.dir.join(format!("{:05}.json", index));
+        fs::write(path, serde_json::to_vec_pretty(fixture)?).await?;
+        Ok(())
+    }
+
+    fn respond(fixture: &Fixture) -> Response<Body> {
+        let mut response = Response::builder().status(fixture.status);
+        for (name, value) in &fixture.headers {
+            response = response.header(name, value);
+        }
+        response
+            .body(Body::from(Bytes::from(fixture.body.clone())))
+            .unwrap()
+    }
+}
+
+struct Replayer {
+    fixtures: Mutex<Vec<Fixture>>,
+}
+
+impl Replayer {
+    fn handle(&self, request: Request<Body>) -> Response<Body> {
+        let method = request.method().to_string();
+        let path = path_and_query(&request);
+
+        let mut fixtures = self.fixtures.lock().unwrap();
+        let position = fixtures
+            .iter()
+            .position(|fixture| fixture.method == method && fixture.path == path);
+        match position {
+            Some(position) => Recorder::respond(&fixtures.remove(position)),
+            None => error_response(
+                StatusCode::NOT_FOUND,
+                format!("No fixture recorded for {} {}", method, path),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::{Outcome, Response as CommandResponse};
+    use crate::octomate::{Batch, ClientOptions, Octomate};
+    use mockito::mock;
+
+    const BATCH: &str = r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - create-label:
+              name: "bug"
+              color: "f29513"
+              description: "Something isn't working"
+"#;
+
+    async fn run_label_batch(address: SocketAddr) -> String {
+        let octomate = Octomate::with_options(
+            "test",
+            &ClientOptions {
+                base_url: Some(format!("http://{}", address)),
+                ..ClientOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+        let batch = Batch::try_from(BATCH.as_bytes()).unwrap();
+        let mut results = octomate.run_batch(&batch).await;
+        match results.remove(0).remove(0).remove(0).remove(0) {
+            Outcome::Succeeded(CommandResponse::CreateLabel(label)) => label.name,
+            _ => panic!("expected a created label"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let dir = std::env::temp_dir().join(format!("octomate-record-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let m = mock("POST", "/repos/me/repo1/labels")
+            .with_status(201)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{
+                    "id": 208045946,
+                    "node_id": "MDU6TGFiZWwyMDgwNDU5NDY=",
+                    "url": "https://api.github.com/repos/me/repo1/labels/bug",
+                    "name": "bug",
+                    "description": "Something isn't working",
+                    "color": "f29513",
+                    "default": true
+                }"#,
+            )
+            .expect(1)
+            .create();
+
+        let recorder = start_recorder(&crate::io::http_client(), &dir, mockito::server_url())
+            .await
+            .unwrap();
+        assert_eq!(run_label_batch(recorder).await, "bug");
+        m.assert();
+        drop(m);
+
+        let fixtures = load_fixtures(&dir).await.unwrap();
+        assert_eq!(fixtures.len(), 1);
+        assert_eq!(fixtures[0].method, "POST");
+        assert_eq!(fixtures[0].path, "/repos/me/repo1/labels");
+
+        let replayer = start_replayer(&dir).await.unwrap();
+        assert_eq!(run_label_batch(replayer).await, "bug");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_record_forwards_custom_headers() {
+        let dir = std::env::temp_dir().join(format!("octomate-headers-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let m = mock("DELETE", "/repos/me/forwarded/labels/wontfix")
+            .match_header("x-github-sso", "partial-results; organizations=21955855")
+            .match_header("authorization", "Bearer test")
+            .with_status(204)
+            .expect(1)
+            .create();
+
+        let recorder = start_recorder(&crate::io::http_client(), &dir, mockito::server_url())
+            .await
+            .unwrap();
+        let octomate = Octomate::with_options(
+            "test",
+            &ClientOptions {
+                base_url: Some(format!("http://{}", recorder)),
+                headers: vec![(
+                    "x-github-sso".to_string(),
+                    "partial-results; organizations=21955855".to_string(),
+                )],
+            },
+        )
+        .await
+        .unwrap();
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: forwarded
+    steps:
+      - runs:
+          - delete-label:
+              name: wontfix
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results = octomate.run_batch(&batch).await;
+
+        assert!(matches!(
+            results[0][0][0][0],
+            Outcome::Succeeded(CommandResponse::DeleteLabel(_))
+        ));
+        m.assert();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
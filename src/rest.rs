@@ -0,0 +1,159 @@
+use crate::error::Error;
+use octocrab::{FromResponse, Octocrab, Page};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+use std::time::Duration;
+
+/// The characters escaped in a segment of a route, the ones of a path plus `/` and `%`.
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Escape `value` as a single segment of a route, like the name of a label.
+pub fn segment(value: &str) -> String {
+    utf8_percent_encode(value, SEGMENT).to_string()
+}
+
+tokio::task_local! {
+    /// Maximum duration of each request sent in the scope of `with_request_timeout`.
+    static REQUEST_TIMEOUT: Option<Duration>;
+}
+
+/// Run `future`, failing each request it sends through this module with `Error::Timeout`
+/// once it takes longer than `timeout`, rather than bounding `future` as a whole.
+pub async fn with_request_timeout<F: Future>(timeout: Option<Duration>, future: F) -> F::Output {
+    REQUEST_TIMEOUT.scope(timeout, future).await
+}
+
+/// Bound `request` by the request timeout of the scope, if there's one.
+/// It's meant for the requests sent by the typed API of octocrab, the other helpers use it.
+pub async fn timed<T, E: Into<Error>>(
+    request: impl Future<Output = Result<T, E>>,
+) -> Result<T, Error> {
+    let timeout = REQUEST_TIMEOUT.try_with(|timeout| *timeout).ok().flatten();
+    match timeout {
+        None => request.await.map_err(Into::into),
+        Some(duration) => match tokio::time::timeout(duration, request).await {
+            Ok(result) => result.map_err(Into::into),
+            Err(_) => Err(Error::Timeout(duration)),
+        },
+    }
+}
+
+/// Send a GET request to `route`, relative to the base url of the API, with the query `parameters`.
+pub async fn get<R: FromResponse>(
+    octocrab: &Octocrab,
+    route: &str,
+    parameters: Option<&(impl Serialize + ?Sized)>,
+) -> Result<R, Error> {
+    let url = octocrab.absolute_url(route)?;
+    timed(read(octocrab._get(url, parameters))).await
+}
+
+/// Every item of `page` and of the pages following it, each page read like `get`.
+pub async fn all_pages<T: DeserializeOwned>(
+    octocrab: &Octocrab,
+    mut page: Page<T>,
+) -> Result<Vec<T>, Error> {
+    let mut items = page.take_items();
+    while let Some(next) = page.next.take() {
+        page = get(octocrab, next.as_str(), None::<&()>).await?;
+        items.append(&mut page.take_items());
+    }
+    Ok(items)
+}
+
+/// Send a POST request with a JSON `body` to `route`.
+pub async fn post<R: FromResponse>(
+    octocrab: &Octocrab,
+    route: &str,
+    body: &impl Serialize,
+) -> Result<R, Error> {
+    let url = octocrab.absolute_url(route)?;
+    timed(read(octocrab._post(url, Some(body)))).await
+}
+
+/// Send a PATCH request with a JSON `body` to `route`.
+pub async fn patch<R: FromResponse>(
+    octocrab: &Octocrab,
+    route: &str,
+    body: &impl Serialize,
+) -> Result<R, Error> {
+    let url = octocrab.absolute_url(route)?;
+    timed(read(octocrab._patch(url, Some(body)))).await
+}
+
+/// Send a PUT request with a JSON `body` to `route`, reading the body of the response.
+pub async fn put_json<R: FromResponse>(
+    octocrab: &Octocrab,
+    route: &str,
+    body: &impl Serialize,
+) -> Result<R, Error> {
+    let url = octocrab.absolute_url(route)?;
+    timed(read(octocrab._put(url, Some(body)))).await
+}
+
+/// Send a DELETE request to `route`.
+/// octocrab expects a JSON body in the response of its typed `delete`,
+/// while most of the DELETE endpoints answer `204 No Content`.
+pub async fn delete(octocrab: &Octocrab, route: &str) -> Result<(), Error> {
+    let url = octocrab.absolute_url(route)?;
+    timed(async { check(octocrab._delete(url, None::<&()>).await?).await }).await?;
+    Ok(())
+}
+
+/// Send a PUT request with a JSON `body` to `route`, ignoring the body of the response.
+/// Like DELETE, many PUT endpoints answer `204 No Content`.
+pub async fn put(octocrab: &Octocrab, route: &str, body: &impl Serialize) -> Result<(), Error> {
+    let url = octocrab.absolute_url(route)?;
+    timed(async { check(octocrab._put(url, Some(body)).await?).await }).await?;
+    Ok(())
+}
+
+/// Read the body of the response to the request, once checked.
+async fn read<R: FromResponse>(
+    request: impl Future<Output = octocrab::Result<reqwest::Response>>,
+) -> Result<R, Error> {
+    let response = check(request.await?).await?;
+    Ok(R::from_response(response).await?)
+}
+
+/// Fail with the status of the response when it isn't a success.
+/// Unlike octocrab, the status is kept, and the body doesn't have to be JSON:
+/// proxies and load balancers answer 502, 503 or 504 with an HTML page.
+async fn check(response: reqwest::Response) -> Result<reqwest::Response, Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let json: Option<serde_json::Value> = serde_json::from_str(&body).ok();
+    let message = json
+        .as_ref()
+        .and_then(|json| json["message"].as_str())
+        .map(str::to_string)
+        .or_else(|| status.canonical_reason().map(str::to_string))
+        .unwrap_or_default();
+    let errors = json
+        .as_ref()
+        .and_then(|json| json["errors"].as_array())
+        .cloned()
+        .unwrap_or_default();
+    Err(Error::Github {
+        status: status.as_u16(),
+        message,
+        errors,
+        empty: body.trim().is_empty(),
+    })
+}
@@ -0,0 +1,166 @@
+use crate::error::Error;
+use paris::warn;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::future::Future;
+use std::time::Duration;
+
+/// How the failed requests are retried, with an exponential backoff.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Number of attempts, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on each retry.
+    pub base: Duration,
+    /// Upper bound of the delay between two attempts.
+    pub max: Duration,
+    /// Fraction of the delay picked at random, between 0 and 1.
+    /// Spreads the retries of concurrent commands.
+    pub jitter: f64,
+    /// Seed of the random part of the delays, to retry the same way on each run.
+    /// Every command then waits the same delays. Seeded from the entropy of the system by default.
+    pub seed: Option<u64>,
+    /// Also retry the idempotent requests failing because what they target is missing,
+    /// as Github may not have caught up yet with an earlier step creating it.
+    pub retry_missing: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base: Duration::from_millis(500),
+            max: Duration::from_secs(30),
+            jitter: 0.5,
+            seed: None,
+            retry_missing: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self {
+            seed: Some(seed),
+            ..self
+        }
+    }
+
+    /// Delay before the retry following the failed `attempt`, counted from 0.
+    /// It lies between `(1 - jitter)` times and once the capped exponential delay.
+    pub fn delay(&self, attempt: u32, rng: &mut impl Rng) -> Duration {
+        let exponential = self
+            .base
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        exponential.mul_f64(1.0 - jitter * rng.gen::<f64>())
+    }
+
+    /// The delays before each retry of a statement, in order.
+    pub fn delays(&self) -> impl Iterator<Item = Duration> + '_ {
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        (0..).map(move |attempt| self.delay(attempt, &mut rng))
+    }
+
+    /// Run `statement` until it succeeds, fails with an error not worth retrying,
+    /// or runs out of attempts. A statement that isn't `idempotent` is only retried when
+    /// the failed request surely changed nothing, so it can't create things twice.
+    pub async fn retry<T, F, Fut>(&self, idempotent: bool, statement: F) -> Result<T, Error>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut attempt = 0;
+        let mut delays = self.delays();
+        loop {
+            match statement().await {
+                Err(err)
+                    if self.is_retryable(&err, idempotent) && attempt + 1 < self.max_attempts =>
+                {
+                    let delay = delays.next().unwrap_or(self.max);
+                    warn!("{}, retrying in {:?}", err, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Whether the request may succeed if sent again.
+    /// Rate limited requests, failed connections and server errors without a body
+    /// were never processed by Github, unlike the requests failing with a server error
+    /// Github answered, which may have been. With `retry_missing`, so are the idempotent
+    /// requests on something missing, like the milestone or the pull request looked up.
+    fn is_retryable(&self, err: &Error, idempotent: bool) -> bool {
+        let missing = err.is_not_found()
+            || matches!(
+                err,
+                Error::MissingMilestone { .. } | Error::MissingPullRequest { .. }
+            );
+        err.is_rate_limited()
+            || err.is_connect()
+            || err.is_empty_server_error()
+            || (idempotent && err.status_code().is_some_and(|status| status >= 500))
+            || (idempotent && self.retry_missing && missing)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_delay_bounds() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base: Duration::from_millis(100),
+            max: Duration::from_millis(1000),
+            jitter: 0.25,
+            seed: None,
+            retry_missing: false,
+        };
+        let mut rng = StdRng::seed_from_u64(42);
+        for attempt in 0..10 {
+            let exponential = Duration::from_millis(100 * 2u64.pow(attempt)).min(policy.max);
+            for _ in 0..100 {
+                let delay = policy.delay(attempt, &mut rng);
+                assert!(delay <= exponential);
+                assert!(delay >= exponential.mul_f64(0.75));
+            }
+        }
+
+        let without_jitter = RetryPolicy {
+            jitter: 0.0,
+            ..policy
+        };
+        assert_eq!(
+            without_jitter.delay(2, &mut rng),
+            Duration::from_millis(400)
+        );
+    }
+
+    #[test]
+    fn test_seeded_delays() {
+        let policy = RetryPolicy::default().with_seed(7);
+        let delays: Vec<Duration> = policy.delays().take(5).collect();
+        assert_eq!(policy.delays().take(5).collect::<Vec<_>>(), delays);
+        assert_eq!(
+            policy
+                .clone()
+                .with_seed(7)
+                .delays()
+                .take(5)
+                .collect::<Vec<_>>(),
+            delays
+        );
+        assert_ne!(
+            policy.with_seed(8).delays().take(5).collect::<Vec<_>>(),
+            delays
+        );
+    }
+}
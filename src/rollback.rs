@@ -0,0 +1,206 @@
+use crate::command::{Command, Outcome};
+use crate::error::Error;
+use crate::io;
+use crate::octomate::{Batch, BatchResult, Job, Repository, Step};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The inverse actions of the commands run by one or more batches, in the order they ran.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct RollbackLog {
+    pub actions: Vec<RollbackAction>,
+    /// The commands that succeeded but can't be undone.
+    pub not_invertible: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RollbackAction {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repository: Option<Repository>,
+    pub command: Command,
+}
+
+impl RollbackLog {
+    /// Record the inverse of every successful command of the batch.
+    pub fn record(&mut self, batch: &Batch, results: &BatchResult) {
+        for (job, job_results) in batch.jobs.iter().zip(results) {
+            for (step, step_results) in job.all_steps().zip(job_results) {
+                for (command, outcomes) in step.runs.iter().zip(step_results) {
+                    for (index, outcome) in outcomes.iter().enumerate() {
+                        let response = match outcome {
+                            Outcome::Succeeded(response) => response,
+                            _ => continue,
+                        };
+                        let repository = command.repository_of(job, index).cloned();
+                        match command.inverse(response) {
+                            Some(command) => self.actions.push(RollbackAction {
+                                repository,
+                                command,
+                            }),
+                            None => self.not_invertible.push(match repository {
+                                Some(repository) => format!(
+                                    "{} on {}/{}",
+                                    command.name(),
+                                    repository.owner,
+                                    repository.name
+                                ),
+                                None => command.name().to_string(),
+                            }),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let bytes = io::read_file(path).await?;
+        Ok(serde_yaml::from_slice(&bytes)?)
+    }
+
+    pub async fn write(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        tokio::fs::write(path, serde_yaml::to_string(self)?).await?;
+        Ok(())
+    }
+
+    /// A batch running the inverse actions in reverse order, one after the other:
+    /// each action is a job needing the previous one.
+    pub fn to_batch(&self) -> Batch {
+        let jobs = self
+            .actions
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(index, action)| Job {
+                name: Some(format!("rollback-{}", index)),
+                needs: index
+                    .checked_sub(1)
+                    .map(|previous| vec![format!("rollback-{}", previous)]),
+                env: None,
+                on_repositories: action.repository.iter().cloned().collect(),
+                on_repository_groups: None,
+                exclude_repositories: None,
+                pre: None,
+                steps: vec![Step {
+                    name: Some(action.command.name().to_string()),
+                    env: None,
+                    retries: None,
+                    runs: vec![action.command.clone()],
+                }],
+                post: None,
+            })
+            .collect();
+        Batch {
+            version: "1.0".to_string(),
+            name: Some("rollback".to_string()),
+            stamp_provenance: None,
+            env: None,
+            repository_groups: None,
+            jobs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::Response;
+    use crate::octomate::{ClientOptions, Octomate};
+    use mockito::mock;
+
+    #[tokio::test]
+    async fn test_create_then_rollback_label() {
+        let create = mock("POST", "/repos/me/repo1/labels")
+            .with_status(201)
+            .with_body(
+                r#"{
+                    "id": 208045946,
+                    "node_id": "MDU6TGFiZWwyMDgwNDU5NDY=",
+                    "url": "https://api.github.com/repos/me/repo1/labels/bug",
+                    "name": "bug",
+                    "description": "Something isn't working",
+                    "color": "f29513",
+                    "default": true
+                }"#,
+            )
+            .expect(1)
+            .create();
+        let delete = mock("DELETE", "/repos/me/repo1/labels/bug")
+            .with_status(204)
+            .expect(1)
+            .create();
+
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+    steps:
+      - runs:
+          - create-label:
+              name: "bug"
+              color: "f29513"
+              description: "Something isn't working"
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let octomate = Octomate::with_options(
+            "test",
+            &ClientOptions {
+                base_url: Some(mockito::server_url()),
+                ..ClientOptions::default()
+            },
+        )
+        .await
+        .unwrap();
+        let results = octomate.run_batch(&batch).await;
+        create.assert();
+
+        let mut log = RollbackLog::default();
+        log.record(&batch, &results);
+        assert_eq!(log.actions.len(), 1);
+        assert!(log.not_invertible.is_empty());
+
+        let path =
+            std::env::temp_dir().join(format!("octomate-rollback-{}.yml", std::process::id()));
+        log.write(&path).await.unwrap();
+        let log = RollbackLog::read(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let results = octomate.run_batch(&log.to_batch()).await;
+        match &results[0][0][0][0] {
+            Outcome::Succeeded(Response::DeleteLabel(name)) => assert_eq!(name, "bug"),
+            _ => panic!("expected the label to be deleted"),
+        }
+        delete.assert();
+    }
+
+    #[test]
+    fn test_rollback_runs_in_reverse_order() {
+        let log: RollbackLog = serde_yaml::from_str(
+            r#"
+actions:
+  - repository: { owner: me, name: repo1 }
+    command:
+      delete-label: { name: first }
+  - repository: { owner: me, name: repo1 }
+    command:
+      delete-label: { name: second }
+not-invertible:
+  - create-issue on me/repo1
+"#,
+        )
+        .unwrap();
+        let batch = log.to_batch();
+        assert_eq!(
+            batch.jobs[0].steps[0].runs,
+            vec![log.actions[1].command.clone()]
+        );
+        assert_eq!(batch.jobs[1].needs, Some(vec!["rollback-0".to_string()]));
+        assert!(Batch::try_from(batch.to_yaml().unwrap().as_bytes()).is_ok());
+    }
+}
@@ -0,0 +1,851 @@
+use crate::command::{Command, Outcome};
+use crate::interactive::Decision;
+use crate::octomate::{Batch, BatchResult, Context, DependencyFailure, Job, Phase, Step};
+use crate::summary::Summary;
+use futures::stream::{FuturesUnordered, StreamExt};
+use futures::FutureExt;
+use octocrab::Octocrab;
+use paris::{error, info};
+use std::collections::{HashMap, VecDeque};
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+
+/// A command of a step on one repository of its job, or once for the commands
+/// not running per repository: the smallest piece of work of a batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unit {
+    pub job: usize,
+    pub step: usize,
+    pub command: usize,
+    /// Index in `on_repositories`, `None` for the commands not running per repository.
+    pub repository: Option<usize>,
+}
+
+impl Unit {
+    pub fn command<'a>(&self, batch: &'a Batch) -> &'a Command {
+        &step(&batch.jobs[self.job], self.step).1.runs[self.command]
+    }
+}
+
+/// What the batches running together, like the jobs streamed from a reader, share to be
+/// scheduled as a single one: at most `max_concurrency` units running at once over all of them,
+/// and all of them cancelled once one is.
+#[derive(Debug, Clone)]
+pub struct SharedRun {
+    permits: Arc<Semaphore>,
+    cancelled: Arc<Mutex<Option<String>>>,
+}
+
+impl SharedRun {
+    pub fn new(max_concurrency: Option<usize>) -> Self {
+        let permits = max_concurrency.unwrap_or(Semaphore::MAX_PERMITS).max(1);
+        Self {
+            permits: Arc::new(Semaphore::new(permits)),
+            cancelled: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Why the run was cancelled, if it was.
+    pub fn cancelled(&self) -> Option<String> {
+        self.cancelled.lock().unwrap().clone()
+    }
+
+    fn cancel(&self, reason: &str) {
+        self.cancelled
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| reason.to_string());
+    }
+}
+
+/// Whether a job can start, given the state of the jobs it needs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Readiness<'a> {
+    Waiting,
+    Ready,
+    /// A needed job failed, directly or through its own needs.
+    Blocked(&'a str),
+}
+
+/// State of a complete job, as seen by the jobs needing it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum JobStatus<'a> {
+    Succeeded,
+    Failed,
+    /// Skipped because of the failure of the given job.
+    Blocked(&'a str),
+}
+
+/// The outcomes of a unit once complete, several for the units of `create-label`.
+type UnitOutcomes = Option<Vec<Outcome>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum JobState {
+    Pending,
+    /// Running the given step, with the number of its units not complete yet.
+    Running {
+        step: usize,
+        remaining: usize,
+    },
+    /// Waiting for the stepper to tell whether to run the given step.
+    Asking {
+        step: usize,
+    },
+    Complete,
+}
+
+/// Run the units of a batch from a single queue, at most `max_concurrency` at once.
+/// A unit is queued once the previous step of its job is complete, which in turn only starts
+/// once the jobs it needs are complete, at most `max_parallel_jobs` jobs at once.
+/// Cancelling the run, because a command failed with `fail_fast` or the batch is aborted,
+/// skips every unit not started yet, but the ones of the `post` steps of the jobs started.
+/// With the `shared` run of the settings, the cap and the cancellation are the ones of the
+/// shared run instead.
+pub struct Scheduler<'a> {
+    batch: &'a Batch,
+    ctx: &'a Context<'a>,
+    /// Outcomes of each unit, by job, step, command and repository.
+    outcomes: Vec<Vec<Vec<Vec<UnitOutcomes>>>>,
+    jobs: Vec<JobState>,
+    statuses: HashMap<&'a str, JobStatus<'a>>,
+    queue: VecDeque<Unit>,
+    /// The step to ask the stepper about, as `(job, step)`, before running anything else.
+    prompt: Option<(usize, usize)>,
+    cancelled: Option<String>,
+}
+
+impl<'a> Scheduler<'a> {
+    pub fn new(ctx: &'a Context<'a>) -> Self {
+        let batch = ctx.batch;
+        let outcomes = batch
+            .jobs
+            .iter()
+            .map(|job| {
+                job.all_steps()
+                    .enumerate()
+                    .map(|(index, step)| {
+                        (0..step.runs.len())
+                            .map(|command| {
+                                std::iter::repeat_with(|| None)
+                                    .take(units(0, job, index, command).len())
+                                    .collect()
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            batch,
+            ctx,
+            outcomes,
+            jobs: vec![JobState::Pending; batch.jobs.len()],
+            statuses: HashMap::new(),
+            queue: VecDeque::new(),
+            prompt: None,
+            cancelled: None,
+        }
+    }
+
+    /// Run every unit of the batch. Results come in the order of the jobs, steps and commands,
+    /// and the outcomes of a command in the order of `on_repositories`.
+    pub async fn run(mut self, octocrab: &'a Octocrab) -> BatchResult {
+        let settings = self.ctx.settings;
+        let max_concurrency = match &settings.shared {
+            Some(_) => usize::MAX,
+            None => settings.max_concurrency.unwrap_or(usize::MAX).max(1),
+        };
+        let mut running = FuturesUnordered::new();
+        loop {
+            if let Some(reason) = settings.shared.as_ref().and_then(SharedRun::cancelled) {
+                self.cancel(reason);
+            }
+            self.start_jobs();
+            // Jobs and steps run one after the other when asking, so nothing runs meanwhile.
+            if let Some((job_index, step_index)) = self.prompt.take() {
+                let decision = self.ask(job_index, step_index).await;
+                self.decide(job_index, step_index, decision);
+                continue;
+            }
+            while running.len() < max_concurrency {
+                match self.queue.pop_front() {
+                    None => break,
+                    Some(unit) => running.push(
+                        AssertUnwindSafe(run_shared_unit(octocrab, self.ctx, unit)).catch_unwind(),
+                    ),
+                }
+            }
+            match running.next().await {
+                None => break,
+                Some(Ok((unit, outcomes))) => self.complete(unit, outcomes),
+                Some(Err(panic)) => {
+                    if let Some(dump) = &settings.dump_on_panic {
+                        let batch = self.batch;
+                        dump.write(&Summary::new(batch, &self.into_results()));
+                    }
+                    std::panic::resume_unwind(panic);
+                }
+            }
+        }
+
+        // Only jobs needing each other, which a valid batch can't have, are left.
+        for index in 0..self.jobs.len() {
+            if self.jobs[index] == JobState::Pending {
+                self.skip_steps(index, 0, "needed jobs never completed");
+            }
+        }
+        self.into_results()
+    }
+
+    /// The outcomes of the units complete so far, in the shape of `BatchResult`.
+    fn into_results(self) -> BatchResult {
+        self.outcomes
+            .into_iter()
+            .map(|steps| {
+                steps
+                    .into_iter()
+                    .map(|commands| {
+                        commands
+                            .into_iter()
+                            .map(|units| units.into_iter().flatten().flatten().collect())
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Skip every unit not started yet for the given reason, keeping the first one.
+    /// The units of the `post` steps still run.
+    pub fn cancel(&mut self, reason: impl Into<String>) {
+        let reason = self.cancelled.get_or_insert(reason.into()).clone();
+        if let Some(shared) = &self.ctx.settings.shared {
+            shared.cancel(&reason);
+        }
+        let batch = self.batch;
+        let (post, skipped): (Vec<Unit>, Vec<Unit>) = self
+            .queue
+            .drain(..)
+            .partition(|unit| step(&batch.jobs[unit.job], unit.step).0 == Phase::Post);
+        self.queue = post.into();
+        for unit in skipped {
+            let outcomes = skip_unit(self.ctx, unit, &reason);
+            self.complete(unit, outcomes);
+        }
+    }
+
+    /// Start the pending jobs whose needs are complete, as long as there's room for them.
+    fn start_jobs(&mut self) {
+        let settings = self.ctx.settings;
+        let max_running = match (&settings.interactive, settings.max_parallel_jobs) {
+            (Some(_), _) => 1,
+            (None, Some(max_parallel_jobs)) => max_parallel_jobs.max(1),
+            (None, None) => usize::MAX,
+        };
+        let mut progressed = true;
+        while progressed {
+            progressed = false;
+            for index in 0..self.jobs.len() {
+                if self.jobs[index] != JobState::Pending {
+                    continue;
+                }
+                if let Some(reason) = self.cancelled.clone() {
+                    self.skip_steps(index, 0, &reason);
+                    progressed = true;
+                    continue;
+                }
+                let running = self
+                    .jobs
+                    .iter()
+                    .filter(|state| {
+                        matches!(state, JobState::Running { .. } | JobState::Asking { .. })
+                    })
+                    .count();
+                if running >= max_running {
+                    return;
+                }
+                let job = &self.batch.jobs[index];
+                match self.readiness(job) {
+                    Readiness::Waiting => continue,
+                    Readiness::Ready => self.start_step(index, 0),
+                    Readiness::Blocked(dependency) => match settings.on_dependency_failure {
+                        DependencyFailure::Run => self.start_step(index, 0),
+                        DependencyFailure::Skip => {
+                            let reason = format!("needed job {} failed", dependency);
+                            self.skip_steps(index, 0, &reason);
+                            if let Some(name) = &job.name {
+                                self.statuses.insert(name, JobStatus::Blocked(dependency));
+                            }
+                        }
+                        DependencyFailure::Abort => {
+                            self.cancel(format!("batch aborted: needed job {} failed", dependency))
+                        }
+                    },
+                }
+                progressed = true;
+            }
+        }
+    }
+
+    fn readiness(&self, job: &'a Job) -> Readiness<'a> {
+        let mut readiness = Readiness::Ready;
+        for need in job.needs() {
+            match self.statuses.get(need.as_str()) {
+                None => readiness = Readiness::Waiting,
+                Some(JobStatus::Succeeded) => {}
+                Some(JobStatus::Failed) => return Readiness::Blocked(need),
+                Some(JobStatus::Blocked(dependency)) => return Readiness::Blocked(dependency),
+            }
+        }
+        readiness
+    }
+
+    /// Queue the units of the step of the job, or of the next step with some units.
+    /// The steps are skipped once the run is cancelled, but the `post` ones,
+    /// or when the stepper says so: the run asks it before queuing the units.
+    fn start_step(&mut self, job_index: usize, step_index: usize) {
+        let job = &self.batch.jobs[job_index];
+        if step_index == 0 {
+            info!("job: {}", job.name.as_deref().unwrap_or("UNAMED"));
+        }
+        let Some((phase, _)) = job.phases().nth(step_index) else {
+            return self.complete_job(job_index);
+        };
+        if let (Some(reason), false) = (self.cancelled.clone(), phase == Phase::Post) {
+            self.skip_step(job_index, step_index, &reason);
+            return self.start_step(job_index, step_index + 1);
+        }
+        if self.ctx.settings.interactive.is_some() {
+            self.jobs[job_index] = JobState::Asking { step: step_index };
+            self.prompt = Some((job_index, step_index));
+            return;
+        }
+        self.queue_step(job_index, step_index);
+    }
+
+    /// Ask the stepper whether to run the step of the job, from a blocking thread
+    /// so the runtime keeps going while the terminal waits for an answer.
+    async fn ask(&self, job_index: usize, step_index: usize) -> Decision {
+        let Some(stepper) = self.ctx.settings.interactive.clone() else {
+            return Decision::Proceed;
+        };
+        let job = self.batch.jobs[job_index].clone();
+        let step = step(&job, step_index).1.clone();
+        let answer = tokio::task::spawn_blocking(move || stepper.ask(&job, &step))
+            .await
+            .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()));
+        answer.unwrap_or_else(|err| {
+            error!("Unable to prompt for the step: {}", err);
+            Decision::Abort
+        })
+    }
+
+    fn decide(&mut self, job_index: usize, step_index: usize, decision: Decision) {
+        match decision {
+            Decision::Proceed => self.queue_step(job_index, step_index),
+            Decision::Skip => {
+                self.skip_step(job_index, step_index, "step skipped");
+                self.start_step(job_index, step_index + 1)
+            }
+            Decision::Abort => {
+                self.cancel("batch aborted");
+                self.skip_step(job_index, step_index, "batch aborted");
+                self.start_step(job_index, step_index + 1)
+            }
+        }
+    }
+
+    fn queue_step(&mut self, job_index: usize, step_index: usize) {
+        let job = &self.batch.jobs[job_index];
+        let step = step(job, step_index).1;
+        info!("step: {}", step.name.as_deref().unwrap_or("UNAMED"));
+        let units: Vec<Unit> = (0..step.runs.len())
+            .flat_map(|command| units(job_index, job, step_index, command))
+            .collect();
+        if units.is_empty() {
+            return self.start_step(job_index, step_index + 1);
+        }
+        self.jobs[job_index] = JobState::Running {
+            step: step_index,
+            remaining: units.len(),
+        };
+        self.queue.extend(units);
+    }
+
+    /// Store the outcomes of the unit, then start the next step of its job once its step
+    /// is complete. Cancel the run if the unit failed and the batch fails fast.
+    fn complete(&mut self, unit: Unit, outcomes: Vec<Outcome>) {
+        let failed = outcomes
+            .iter()
+            .any(|outcome| matches!(outcome, Outcome::Failed(_)));
+        self.outcomes[unit.job][unit.step][unit.command][unit.repository.unwrap_or(0)] =
+            Some(outcomes);
+        if let JobState::Running { step, remaining } = self.jobs[unit.job] {
+            self.jobs[unit.job] = JobState::Running {
+                step,
+                remaining: remaining - 1,
+            };
+            if remaining == 1 {
+                self.start_step(unit.job, step + 1);
+            }
+        }
+        if failed && self.ctx.settings.fail_fast {
+            let command = unit.command(self.batch);
+            self.cancel(format!("batch cancelled: {} failed", command.name()));
+        }
+    }
+
+    fn complete_job(&mut self, job_index: usize) {
+        self.jobs[job_index] = JobState::Complete;
+        let job = &self.batch.jobs[job_index];
+        if let Some(name) = &job.name {
+            let failed = self.outcomes[job_index]
+                .iter()
+                .flatten()
+                .flatten()
+                .flatten()
+                .flatten()
+                .any(|outcome| matches!(outcome, Outcome::Failed(_)));
+            let status = match failed {
+                true => JobStatus::Failed,
+                false => JobStatus::Succeeded,
+            };
+            self.statuses.insert(name, status);
+        }
+    }
+
+    /// Skip the steps of the job from `step_index` on, which completes it.
+    fn skip_steps(&mut self, job_index: usize, step_index: usize, reason: &str) {
+        for index in step_index..self.batch.jobs[job_index].all_steps().count() {
+            self.skip_step(job_index, index, reason);
+        }
+        self.complete_job(job_index);
+    }
+
+    fn skip_step(&mut self, job_index: usize, step_index: usize, reason: &str) {
+        let job = &self.batch.jobs[job_index];
+        for command in 0..step(job, step_index).1.runs.len() {
+            for unit in units(job_index, job, step_index, command) {
+                self.outcomes[job_index][step_index][command][unit.repository.unwrap_or(0)] =
+                    Some(skip_unit(self.ctx, unit, reason));
+            }
+        }
+    }
+}
+
+/// The units of a command of a step: one per repository of the job,
+/// or a single one if the command doesn't run per repository or the job has no repository.
+fn units(job_index: usize, job: &Job, step: usize, command: usize) -> Vec<Unit> {
+    let unit = |repository| Unit {
+        job: job_index,
+        step,
+        command,
+        repository,
+    };
+    let per_repository = self::step(job, step).1.runs[command].is_per_repository();
+    match per_repository && !job.on_repositories.is_empty() {
+        true => (0..job.on_repositories.len()).map(Some).map(unit).collect(),
+        false => vec![unit(None)],
+    }
+}
+
+/// The step at `index` of `Job::phases`, with its phase.
+fn step(job: &Job, index: usize) -> (Phase, &Step) {
+    job.phases()
+        .nth(index)
+        .expect("the units are only made of the steps of their job")
+}
+
+/// The context of the unit, restricted to its repository.
+fn unit_context<'a>(ctx: &Context<'a>, unit: Unit) -> Context<'a> {
+    let job = &ctx.batch.jobs[unit.job];
+    let ctx = ctx
+        .update_from_job(job)
+        .update_from_step(step(job, unit.step).1);
+    match unit.repository {
+        None => ctx,
+        Some(index) => ctx.update_from_repository(&job.on_repositories[index]),
+    }
+}
+
+async fn run_unit(octocrab: &Octocrab, ctx: &Context<'_>, unit: Unit) -> (Unit, Vec<Outcome>) {
+    let ctx = unit_context(ctx, unit);
+    (unit, unit.command(ctx.batch).run(octocrab, &ctx).await)
+}
+
+/// Run the unit once the shared run has a permit for it, if there's one.
+/// It's skipped if the shared run was cancelled meanwhile, but for the units of `post` steps.
+async fn run_shared_unit(
+    octocrab: &Octocrab,
+    ctx: &Context<'_>,
+    unit: Unit,
+) -> (Unit, Vec<Outcome>) {
+    let Some(shared) = &ctx.settings.shared else {
+        return run_unit(octocrab, ctx, unit).await;
+    };
+    let _permit = shared.permits.acquire().await.expect("never closed");
+    match shared.cancelled() {
+        Some(reason) if step(&ctx.batch.jobs[unit.job], unit.step).0 != Phase::Post => {
+            (unit, skip_unit(ctx, unit, &reason))
+        }
+        _ => run_unit(octocrab, ctx, unit).await,
+    }
+}
+
+fn skip_unit(ctx: &Context<'_>, unit: Unit, reason: &str) -> Vec<Outcome> {
+    let ctx = unit_context(ctx, unit);
+    unit.command(ctx.batch).skip(&ctx, reason)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::octomate::{ClientOptions, Octomate, Settings};
+    use crate::summary::PanicDump;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server};
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    const LABEL: &str = r#"{
+        "id": 208045946,
+        "node_id": "MDU6TGFiZWwyMDgwNDU5NDY=",
+        "url": "https://api.github.com/repos/me/repo1/labels/bug",
+        "name": "bug",
+        "description": "Something isn't working",
+        "color": "f29513",
+        "default": true
+    }"#;
+
+    /// What the Github stand-in saw: the requests in the order they came in,
+    /// and the most requests in flight at once.
+    #[derive(Default)]
+    struct Github {
+        requests: Mutex<Vec<String>>,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    /// Answer each request after a while, so the units running at once overlap,
+    /// with a validation error for the repositories, or the labels, named `broken`.
+    async fn serve(github: Arc<Github>) -> String {
+        let make_service = make_service_fn(move |_| {
+            let github = github.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let github = github.clone();
+                    async move {
+                        let request = format!("{} {}", request.method(), request.uri().path());
+                        let count = github.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        github.max_in_flight.fetch_max(count, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(30)).await;
+                        github.in_flight.fetch_sub(1, Ordering::SeqCst);
+                        github.requests.lock().unwrap().push(request.clone());
+                        let response = match request.as_str() {
+                            request
+                                if request.contains("/broken/") || request.ends_with("/broken") =>
+                            {
+                                Response::builder()
+                                    .status(422)
+                                    .body(Body::from(r#"{"message": "Validation Failed"}"#))
+                            }
+                            request if request.starts_with("DELETE") => {
+                                Response::builder().status(204).body(Body::empty())
+                            }
+                            _ => Response::builder().status(201).body(Body::from(LABEL)),
+                        };
+                        Ok::<_, Infallible>(response.unwrap())
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let address = server.local_addr();
+        tokio::spawn(server);
+        format!("http://{}", address)
+    }
+
+    async fn run(settings: Settings, batch: &str) -> (Arc<Github>, BatchResult) {
+        let github = Arc::new(Github::default());
+        let octomate = Octomate::with_options(
+            "test",
+            &ClientOptions {
+                base_url: Some(serve(github.clone()).await),
+                ..ClientOptions::default()
+            },
+        )
+        .await
+        .unwrap()
+        .with_settings(settings);
+        let batch = Batch::try_from(batch.as_bytes()).unwrap();
+        let results = octomate.run_batch(&batch).await;
+        (github, results)
+    }
+
+    fn describe(outcome: &Outcome) -> String {
+        match outcome {
+            Outcome::Succeeded(_) => "succeeded".to_string(),
+            Outcome::Failed(_) => "failed".to_string(),
+            Outcome::Skipped(reason) => reason.clone(),
+            Outcome::DryRun(description) => description.clone(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_steps_run_in_order() {
+        let (github, results) = run(
+            Settings::default(),
+            r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+      - owner: me
+        name: repo2
+    steps:
+      - runs:
+          - create-label:
+              name: bug
+              color: "f29513"
+              description: bug
+      - runs:
+          - delete-label:
+              name: wontfix
+"#,
+        )
+        .await;
+
+        let requests = github.requests.lock().unwrap();
+        assert_eq!(requests.len(), 4);
+        assert!(requests[..2]
+            .iter()
+            .all(|request| request.starts_with("POST")));
+        assert!(requests[2..]
+            .iter()
+            .all(|request| request.starts_with("DELETE")));
+        // Both repositories of a step still run at once.
+        assert_eq!(github.max_in_flight.load(Ordering::SeqCst), 2);
+        assert_eq!(results[0].len(), 2);
+        assert!(results[0]
+            .iter()
+            .flatten()
+            .flatten()
+            .all(|outcome| describe(outcome) == "succeeded"));
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrency() {
+        let job = |name: &str| {
+            format!(
+                r#"
+  - on-repositories:
+      - owner: me
+        name: {}1
+      - owner: me
+        name: {}2
+      - owner: me
+        name: {}3
+    steps:
+      - runs:
+          - create-label:
+              name: bug
+              color: "f29513"
+              description: bug
+          - delete-label:
+              name: wontfix"#,
+                name, name, name
+            )
+        };
+        let (github, results) = run(
+            Settings {
+                max_concurrency: Some(3),
+                ..Settings::default()
+            },
+            &format!("version: \"1.0\"\njobs:{}{}\n", job("front"), job("back")),
+        )
+        .await;
+
+        assert_eq!(github.requests.lock().unwrap().len(), 12);
+        assert_eq!(github.max_in_flight.load(Ordering::SeqCst), 3);
+        assert_eq!(results[1][0][1].len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_fail_fast() {
+        let (github, results) = run(
+            Settings {
+                fail_fast: true,
+                max_concurrency: Some(1),
+                ..Settings::default()
+            },
+            r#"
+version: "1.0"
+jobs:
+  - name: first
+    on-repositories:
+      - owner: me
+        name: broken
+      - owner: me
+        name: repo2
+    steps:
+      - runs:
+          - delete-label:
+              name: wontfix
+      - runs:
+          - delete-label:
+              name: duplicate
+  - name: second
+    needs: [first]
+    on-repositories:
+      - owner: me
+        name: repo3
+    steps:
+      - runs:
+          - delete-label:
+              name: wontfix
+"#,
+        )
+        .await;
+
+        assert_eq!(
+            *github.requests.lock().unwrap(),
+            vec!["DELETE /repos/me/broken/labels/wontfix"]
+        );
+        let outcomes: Vec<Vec<String>> = results
+            .iter()
+            .flatten()
+            .flatten()
+            .map(|outcomes| outcomes.iter().map(describe).collect())
+            .collect();
+        let cancelled = "batch cancelled: delete-label failed";
+        assert_eq!(
+            outcomes,
+            vec![
+                vec!["failed", cancelled],
+                vec![cancelled, cancelled],
+                vec![cancelled],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_post_steps_always_run() {
+        let batch = r#"
+version: "1.0"
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+    pre:
+      - runs:
+          - create-label:
+              name: bug
+              color: "f29513"
+              description: bug
+    steps:
+      - runs:
+          - delete-label:
+              name: broken
+      - runs:
+          - delete-label:
+              name: wontfix
+    post:
+      - runs:
+          - delete-label:
+              name: bug
+"#;
+        let (github, results) = run(
+            Settings {
+                fail_fast: true,
+                ..Settings::default()
+            },
+            batch,
+        )
+        .await;
+
+        assert_eq!(
+            *github.requests.lock().unwrap(),
+            vec![
+                "POST /repos/me/repo1/labels",
+                "DELETE /repos/me/repo1/labels/broken",
+                "DELETE /repos/me/repo1/labels/bug",
+            ]
+        );
+        let outcomes: Vec<Vec<String>> = results
+            .iter()
+            .flatten()
+            .flatten()
+            .map(|outcomes| outcomes.iter().map(describe).collect())
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                vec!["succeeded"],
+                vec!["failed"],
+                vec!["batch cancelled: delete-label failed"],
+                vec!["succeeded"],
+            ]
+        );
+
+        let summary = Summary::new(&Batch::try_from(batch.as_bytes()).unwrap(), &results);
+        assert_eq!(summary.post.succeeded, 1);
+        assert_eq!(summary.total.failed, 1);
+    }
+
+    #[tokio::test]
+    async fn test_dump_results_on_panic() {
+        let path = std::env::temp_dir().join(format!("octomate-panic-{}.json", std::process::id()));
+        let octomate = Octomate::new("test")
+            .await
+            .unwrap()
+            .with_settings(Settings {
+                dry_run: true,
+                dump_on_panic: Some(PanicDump {
+                    path: Some(path.clone()),
+                }),
+                ..Settings::default()
+            })
+            .on_command_complete(|info, _| {
+                if info.step == Some("explode") {
+                    panic!("the command exploded");
+                }
+            });
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+name: fragile
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+      - owner: me
+        name: repo2
+    steps:
+      - runs:
+          - delete-label:
+              name: wontfix
+      - name: explode
+        runs:
+          - delete-label:
+              name: duplicate
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let run = AssertUnwindSafe(octomate.run_batch(&batch))
+            .catch_unwind()
+            .await;
+        assert!(run.is_err());
+        let dump: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(dump["batch"], "fragile");
+        assert_eq!(dump["dry_run"], 2);
+        assert_eq!(dump["by_command"]["delete-label"]["dry_run"], 2);
+    }
+}
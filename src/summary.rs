@@ -0,0 +1,280 @@
+use crate::command::Outcome;
+use crate::octomate::{Batch, BatchResult, Phase};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Counts of the outcomes of some commands.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Counts {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub dry_run: usize,
+}
+
+impl Counts {
+    /// Number of outcomes counted.
+    pub fn total(&self) -> usize {
+        self.succeeded + self.failed + self.skipped + self.dry_run
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+
+    /// Count the outcomes of `other` too, like the ones of another batch.
+    pub fn merge(&mut self, other: &Counts) {
+        self.succeeded += other.succeeded;
+        self.failed += other.failed;
+        self.skipped += other.skipped;
+        self.dry_run += other.dry_run;
+    }
+
+    fn add(&mut self, outcome: &Outcome) {
+        match outcome {
+            Outcome::Succeeded(_) => self.succeeded += 1,
+            Outcome::Failed(_) => self.failed += 1,
+            Outcome::Skipped(_) => self.skipped += 1,
+            Outcome::DryRun(_) => self.dry_run += 1,
+        }
+    }
+}
+
+impl std::fmt::Display for Counts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} succeeded, {} failed", self.succeeded, self.failed)?;
+        if self.skipped > 0 {
+            write!(f, ", {} skipped", self.skipped)?;
+        }
+        if self.dry_run > 0 {
+            write!(f, ", {} dry-run", self.dry_run)?;
+        }
+        Ok(())
+    }
+}
+
+/// The failures tolerated before the run counts as failed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureThreshold {
+    /// At most this number of failed commands.
+    Count(usize),
+    /// At most this fraction of the commands failed, between 0 and 1.
+    Rate(f64),
+}
+
+impl FailureThreshold {
+    pub fn is_exceeded(&self, counts: &Counts) -> bool {
+        match *self {
+            Self::Count(max) => counts.failed > max,
+            Self::Rate(rate) => counts.failed as f64 > rate * counts.total() as f64,
+        }
+    }
+}
+
+impl std::fmt::Display for FailureThreshold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Count(max) => write!(f, "{} failure(s)", max),
+            Self::Rate(rate) => write!(f, "{}% of the commands", rate * 100.0),
+        }
+    }
+}
+
+/// Counts of the commands run by a batch.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    pub batch: String,
+    #[serde(flatten)]
+    pub total: Counts,
+    /// Counts of each kind of command, by command name.
+    pub by_command: BTreeMap<String, Counts>,
+    /// Counts of the commands of the `post` steps, also in the total.
+    #[serde(skip_serializing_if = "Counts::is_empty")]
+    pub post: Counts,
+    pub failures: Vec<String>,
+}
+
+impl Summary {
+    pub fn new(batch: &Batch, results: &BatchResult) -> Self {
+        let mut summary = Self {
+            batch: batch.display_name(),
+            ..Self::default()
+        };
+        summary.add(batch, results);
+        summary
+    }
+
+    /// Count the outcomes of more results, like the ones of each job of a stream.
+    pub fn add(&mut self, batch: &Batch, results: &BatchResult) {
+        for (job, job_results) in batch.jobs.iter().zip(results) {
+            for ((phase, step), step_results) in job.phases().zip(job_results) {
+                for (command, outcomes) in step.runs.iter().zip(step_results) {
+                    let counts = self
+                        .by_command
+                        .entry(command.name().to_string())
+                        .or_default();
+                    for outcome in outcomes {
+                        counts.add(outcome);
+                        self.total.add(outcome);
+                        if phase == Phase::Post {
+                            self.post.add(outcome);
+                        }
+                        if let Outcome::Failed(err) = outcome {
+                            self.failures.push(err.to_string());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Count a failure happening outside of any command.
+    pub fn add_failure(&mut self, failure: impl ToString) {
+        self.total.failed += 1;
+        self.failures.push(failure.to_string());
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.total.failed > 0
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Batch {}: {}", self.batch, self.total)
+    }
+}
+
+/// Where to write the summary of the results collected so far when a command panics,
+/// before the panic goes on: the results of the other commands would be lost otherwise.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PanicDump {
+    /// File to write the summary to, stderr if `None`.
+    pub path: Option<PathBuf>,
+}
+
+impl PanicDump {
+    /// Write the summary as JSON. It's already panicking, so failing to write it is only logged.
+    pub fn write(&self, summary: &Summary) {
+        let result = serde_json::to_string_pretty(summary)
+            .map_err(std::io::Error::from)
+            .and_then(|json| match &self.path {
+                None => writeln!(std::io::stderr(), "{}", json),
+                Some(path) => std::fs::write(path, json + "\n"),
+            });
+        if let Err(err) = result {
+            eprintln!("Unable to dump the results: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::command::Response;
+    use crate::error::Error;
+
+    #[test]
+    fn test_summary_of_each_outcome() {
+        let label: octocrab::models::Label = serde_json::from_value(serde_json::json!({
+            "id": 208045946,
+            "node_id": "MDU6TGFiZWwyMDgwNDU5NDY=",
+            "url": "https://api.github.com/repos/me/repo1/labels/bug",
+            "name": "bug",
+            "description": "Something isn't working",
+            "color": "f29513",
+            "default": true
+        }))
+        .unwrap();
+        let batch = Batch::try_from(
+            r#"
+version: "1.0"
+name: labels
+jobs:
+  - on-repositories:
+      - owner: me
+        name: repo1
+      - owner: me
+        name: repo2
+    steps:
+      - runs:
+          - create-label:
+              name: bug
+              color: "f29513"
+              description: "Something isn't working"
+          - delete-label:
+              name: wontfix
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let results: BatchResult = vec![vec![vec![
+            vec![
+                Outcome::Succeeded(Response::CreateLabel(label)),
+                Outcome::Failed(Error::Validation("invalid".to_string())),
+            ],
+            vec![
+                Outcome::Skipped("not found".to_string()),
+                Outcome::DryRun("delete-label on me/repo2".to_string()),
+            ],
+        ]]];
+
+        let summary = Summary::new(&batch, &results);
+        assert_eq!(
+            summary.total,
+            Counts {
+                succeeded: 1,
+                failed: 1,
+                skipped: 1,
+                dry_run: 1,
+            }
+        );
+        assert_eq!(
+            summary.to_string(),
+            "Batch labels: 1 succeeded, 1 failed, 1 skipped, 1 dry-run"
+        );
+        assert_eq!(
+            summary.by_command["create-label"].to_string(),
+            "1 succeeded, 1 failed"
+        );
+        assert_eq!(
+            serde_json::to_value(&summary).unwrap(),
+            serde_json::json!({
+                "batch": "labels",
+                "succeeded": 1,
+                "failed": 1,
+                "skipped": 1,
+                "dry_run": 1,
+                "by_command": {
+                    "create-label": { "succeeded": 1, "failed": 1, "skipped": 0, "dry_run": 0 },
+                    "delete-label": { "succeeded": 0, "failed": 0, "skipped": 1, "dry_run": 1 }
+                },
+                "failures": ["Invalid batch: invalid"]
+            })
+        );
+    }
+
+    #[test]
+    fn test_failure_threshold() {
+        let counts = |failed| Counts {
+            succeeded: 100 - failed,
+            failed,
+            ..Counts::default()
+        };
+
+        let threshold = FailureThreshold::Count(2);
+        assert!(!threshold.is_exceeded(&counts(1)));
+        assert!(!threshold.is_exceeded(&counts(2)));
+        assert!(threshold.is_exceeded(&counts(3)));
+
+        let threshold = FailureThreshold::Rate(0.05);
+        assert!(!threshold.is_exceeded(&counts(4)));
+        assert!(!threshold.is_exceeded(&counts(5)));
+        assert!(threshold.is_exceeded(&counts(6)));
+        assert_eq!(threshold.to_string(), "5% of the commands");
+
+        assert!(FailureThreshold::Count(0).is_exceeded(&counts(1)));
+    }
+}